@@ -1,8 +1,14 @@
 use super::{
     config::config_cli,
-    push::*
+    output::format_arg,
+    push::*,
+    status::{stale_after_arg, status_cli},
 };
+use std::path::PathBuf;
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use tracing_appender::rolling::Rotation;
+use crate::executor::ExecutorOptions;
+use crate::log::{self, LogFormat, LogOptions};
 
 pub fn main_cli() -> Command {
     Command::new("Dionysius")
@@ -12,16 +18,73 @@ pub fn main_cli() -> Command {
         // .subcommand(list_cli())
         .subcommand(config_cli())
         .subcommand(push_cli())
+        .subcommand(status_cli())
         .subcommand(test_cli())
+        .arg(format_arg())
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Log more, `-vv` for everything")
+                .global(true)
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Log only warnings, `-qq` only errors")
+                .global(true)
+                .conflicts_with("verbose")
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("Also log to FILE, rotated as `--log-rotation` tells")
+                .global(true)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("log-rotation")
+                .long("log-rotation")
+                .value_name("PERIOD")
+                .value_parser(["hourly", "daily", "never"])
+                .default_value("daily")
+                .help("Start a new log file every hour or day, keeping the last 7, suffixed with the date")
+                .global(true)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Log as text or one JSON object per line")
+                .global(true)
+                .action(ArgAction::Set),
+        )
         .arg(
             Arg::new("threads")
                 .short('t')
                 .long("threads")
                 .value_name("Thread Number")
                 .value_parser(value_parser!(usize))
-                .help("Sets the maximum number of threads to use")
+                .help("Sets the maximum number of tasks running at once, the number of CPUs by default")
+                .global(true)
                 .action(ArgAction::Set),
         )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .value_name("KIND=N")
+                .value_parser(parse_kind_limit)
+                .help("Run at most N tasks of a backend at once, e.g. `git=2` (git defaults to 4)")
+                .global(true)
+                .action(ArgAction::Append),
+        )
 }
 
 pub fn push_cli() -> Command {
@@ -49,6 +112,28 @@ pub fn push_cli() -> Command {
                 .help("Exclude pattern to be added to the tasks")
                 .action(ArgAction::Append)
         )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Save unsaved work wherever `on_unsave` is `ask`, without asking")
+                .conflicts_with_all(["no", "ask"])
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no")
+                .long("no")
+                .help("Leave unsaved work alone wherever `on_unsave` is `ask`, without asking")
+                .conflicts_with("ask")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("ask")
+                .long("ask")
+                .help("Review unsaved work before running, or use `on_unsave_unattended` without a terminal (default)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(stale_after_arg())
         .arg(
             Arg::new("search-hidden")
                 .short('H')
@@ -58,17 +143,47 @@ pub fn push_cli() -> Command {
         )
         .subcommand(push_git_cli())
         .subcommand(push_borg_cli())
+        .subcommand(push_rsync_cli())
+        .subcommand(push_tar_cli())
+        .subcommand(push_restic_cli())
         .subcommand(push_trigger_cli())
 }
 
-pub fn set_threads(matches: &ArgMatches) {
-    // 设置线程池
-    // if let Some(num_threads) = matches.get_one::<usize>("threads") {
-    //     ThreadPoolBuilder::new()
-    //         .num_threads(*num_threads)
-    //         .build_global()
-    //         .expect("Failed to build thread pool");
-    // }
+pub fn log_options(matches: &ArgMatches) -> LogOptions {
+    LogOptions {
+        level: log::level_from_counts(matches.get_count("verbose"), matches.get_count("quiet")),
+        format: match matches.get_one::<String>("log-format").map(String::as_str) {
+            Some("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        },
+        file: matches.get_one::<String>("log-file").map(PathBuf::from),
+        rotation: match matches.get_one::<String>("log-rotation").map(String::as_str) {
+            Some("hourly") => Rotation::HOURLY,
+            Some("never") => Rotation::NEVER,
+            _ => Rotation::DAILY,
+        },
+    }
+}
+
+pub fn executor_options(matches: &ArgMatches) -> ExecutorOptions {
+    let mut options = ExecutorOptions::default();
+    if let Some(threads) = matches.get_one::<usize>("threads") {
+        options.threads = *threads;
+    }
+    for (kind, limit) in matches.get_many::<(String, usize)>("limit").unwrap_or_default() {
+        options.kind_limits.insert(kind.clone(), *limit);
+    }
+    options
+}
+
+/// `KIND=N` of `--limit`.
+fn parse_kind_limit(value: &str) -> Result<(String, usize), String> {
+    let (kind, limit) = value.split_once('=').ok_or("expected KIND=N, e.g. `git=2`")?;
+    let limit: usize = limit.parse().map_err(|_| format!("`{}` is not a number", limit))?;
+    if limit == 0 {
+        return Err("the limit must be at least 1".to_string());
+    }
+    Ok((kind.to_string(), limit))
 }
 
 pub fn test_cli() -> Command {
@@ -1,5 +1,7 @@
 pub mod entry;
 pub mod push;
 pub mod config;
+pub mod output;
+pub mod status;
 
 // pub use crate::cli::entry::main_cli;
@@ -0,0 +1,67 @@
+use clap::{Arg, ArgAction, ArgMatches};
+use serde::Serialize;
+use crate::task::{SummaryCounts, TaskDescriptor, OUTPUT_VERSION};
+
+/// How subcommands print their results, see `--format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    /// One document with every task, and the summary of a run.
+    Json,
+    /// One task per line, then the summary of a run.
+    Ndjson,
+}
+
+pub fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_name("FORMAT")
+        .value_parser(["text", "json", "ndjson"])
+        .default_value("text")
+        .global(true)
+        .help("Print results as text, one JSON document or JSON lines")
+        .action(ArgAction::Set)
+}
+
+impl OutputFormat {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        match matches.get_one::<String>("format").map(String::as_str) {
+            Some("json") => OutputFormat::Json,
+            Some("ndjson") => OutputFormat::Ndjson,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    tasks: &'a [TaskDescriptor],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<SummaryCounts>,
+}
+
+#[derive(Serialize)]
+struct SummaryLine {
+    version: u32,
+    summary: SummaryCounts,
+}
+
+/// Print `tasks`, and the `summary` of a run if there was one, in a JSON `format`.
+pub fn emit(format: OutputFormat, tasks: &[TaskDescriptor], summary: Option<SummaryCounts>) {
+    match format {
+        OutputFormat::Json => {
+            let document = Document { version: OUTPUT_VERSION, tasks, summary };
+            println!("{}", serde_json::to_string_pretty(&document).unwrap());
+        },
+        OutputFormat::Ndjson => {
+            for task in tasks {
+                println!("{}", serde_json::to_string(task).unwrap());
+            }
+            if let Some(summary) = summary {
+                println!("{}", serde_json::to_string(&SummaryLine { version: OUTPUT_VERSION, summary }).unwrap());
+            }
+        },
+        OutputFormat::Text => unreachable!("text is printed by each subcommand"),
+    }
+}
@@ -1,6 +1,13 @@
 use std::path::{absolute, PathBuf};
 use clap::{Arg, ArgAction, ArgMatches, Command};
-use crate::task::{self, CliTaskConfig, TaskList};
+use colored::Colorize;
+use crate::journal::{Journal, JournalEntry};
+use crate::executor::run_tasks;
+use crate::log::{log_debug, log_error, log_warn};
+use crate::task::{self, AnswerPolicy, CliTaskConfig, RunSummary};
+use crate::traversal::Traversal;
+use super::entry::executor_options;
+use super::output::{self, OutputFormat};
 
 pub fn push_trigger_cli() -> Command {
     Command::new("trigger")
@@ -51,54 +58,146 @@ pub fn push_borg_cli() -> Command {
         )
 }
 
+pub fn push_rsync_cli() -> Command {
+    Command::new("rsync")
+        .about("Synchronize to rsync targets")
+        .arg(
+            Arg::new("directory")
+                .short('d')
+                .long("directory")
+                .value_name("DIR")
+                .help("Sets the path to synchronize by rsync")
+                .action(ArgAction::Set)
+                .required(true)
+        )
+}
+
+pub fn push_tar_cli() -> Command {
+    Command::new("tar")
+        .about("Archive to tarballs")
+        .arg(
+            Arg::new("directory")
+                .short('d')
+                .long("directory")
+                .value_name("DIR")
+                .help("Sets the path to archive by tar")
+                .action(ArgAction::Set)
+                .required(true)
+        )
+}
+
+pub fn push_restic_cli() -> Command {
+    Command::new("restic")
+        .about("Push to restic repositories")
+        .arg(
+            Arg::new("directory")
+                .short('d')
+                .long("directory")
+                .value_name("DIR")
+                .help("Sets the path to back up by restic")
+                .action(ArgAction::Set)
+                .required(true)
+        )
+}
+
 // TODO: add inheritation of trigger_by
 pub async fn push_main(parent_matches: &ArgMatches, matches: &ArgMatches, task_type_id: &'static str) {
     let dir = matches.get_one::<String>("directory").unwrap();
     
     let search_hidden = parent_matches.get_flag("search-hidden");
     let cli_exclude_patterns: Vec<String> = parent_matches.get_many::<String>("exclude").unwrap_or_default().cloned().collect();
+    let root_dir = absolute(PathBuf::from(dir)).unwrap();
     let user_cli_config = CliTaskConfig {
         search_hidden,
         exclude_patterns: cli_exclude_patterns,
+        root_dir: root_dir.clone(),
     };
     
     let execute_mode = parent_matches.get_flag("execute");
     let preview_mode = parent_matches.get_flag("preview");
+    let format = OutputFormat::from_matches(matches);
+    let stale_after = *parent_matches.get_one::<chrono::TimeDelta>("stale-after").unwrap();
 
     // 收集任务
-    let task_list: TaskList = vec![];
-    let task_list_ref = std::sync::Arc::new(std::sync::Mutex::new(task_list));
-    
-    task::collect_tasks(
-        task_type_id,
-        absolute(PathBuf::from(dir)).unwrap(),
-        task_list_ref.clone(),
-        None,
-        None,
-        user_cli_config
-    ).await.inspect_err(|e| {
-        eprintln!("{:?}", e);
-    }).unwrap();
-
-    let result = task_list_ref.lock().unwrap();
+    let traversal = Traversal::new(executor_options(parent_matches).threads);
+    traversal.cancel_on_ctrl_c();
+    let Ok(mut result) = task::collect_all_tasks(task_type_id, user_cli_config, &traversal).await else {
+        log_error!("Interrupted while collecting tasks, nothing was run.");
+        std::process::exit(130);
+    };
+    let skipped = traversal.errors();
+    for err in skipped.iter() {
+        log_warn!("Skipped {}", err);
+    }
+    log_debug!("Collected {} tasks under {}.", result.len(), dir);
     
-    if preview_mode {
+    if preview_mode && format != OutputFormat::Text {
+        let descriptors: Vec<_> = result.iter().map(|task| {
+            let mut descriptor = task.describe();
+            descriptor.stale = Some(JournalEntry::is_stale(descriptor.journal.as_ref(), stale_after));
+            descriptor
+        }).collect();
+        output::emit(format, &descriptors, None);
+    } else if preview_mode {
+        let journal = Journal::default_location();
         for task in result.iter() {
             task.preview().unwrap();
+            let entry = journal.load(task.kind(), task.source());
+            if JournalEntry::is_stale(entry.as_ref(), stale_after) {
+                println!("  {} {}", JournalEntry::describe_age(entry.as_ref()), "(stale)".red());
+            } else {
+                println!("  {}", JournalEntry::describe_age(entry.as_ref()));
+            }
         }
     } else {
-        let mut command_list = if execute_mode {
-            None
+        let policy = if parent_matches.get_flag("yes") {
+            AnswerPolicy::Yes
+        } else if parent_matches.get_flag("no") {
+            AnswerPolicy::No
         } else {
-            Some(Vec::new())
+            AnswerPolicy::Ask
         };
-        
-        for task in result.iter() {
-            task.execute(&mut command_list);
+        task::review_tasks(&mut result, policy);
+
+        // described before anything runs, as the preview would
+        let mut descriptors: Vec<_> = if format != OutputFormat::Text {
+            result.iter().map(|task| task.describe()).collect()
+        } else {
+            Vec::new()
+        };
+        let runs = run_tasks(std::mem::take(&mut result), !execute_mode, &executor_options(parent_matches)).await;
+
+        let mut summary = RunSummary::default();
+        let mut command_list = Vec::new();
+        let journal = Journal::default_location();
+        for (index, run) in runs.into_iter().enumerate() {
+            if execute_mode
+                && let Err(err) = journal.record(run.task.as_ref(), run.started, run.duration, &run.result)
+            {
+                log_warn!("Failed to record the run of {} in the journal: {}", run.task.source().display(), err);
+            }
+            if let Some(descriptor) = descriptors.get_mut(index) {
+                descriptor.commands = run.commands.clone();
+                descriptor.outcome = Some((&run.result).into());
+            }
+            command_list.extend(run.commands.into_iter().flatten());
+            summary.record(run.task.as_ref(), run.result);
         }
 
-        if let Some(commands) = command_list {
-            println!("{}", commands.join("\n"));
+        if format != OutputFormat::Text {
+            output::emit(format, &descriptors, Some(summary.counts()));
+        } else {
+            if !execute_mode {
+                println!("{}", command_list.join("\n"));
+            }
+            eprintln!("{}", summary);
+            if !skipped.is_empty() {
+                eprintln!("{} directories could not be visited.", skipped.len());
+            }
+        }
+        // a directory that could not be visited was not backed up either
+        if summary.has_failure() || !skipped.is_empty() {
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,249 @@
+use std::path::{absolute, Path, PathBuf};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use colored::Colorize;
+use crate::handlers::git::{git_repo_status, GitRepoStatus, HeadState};
+use crate::journal::{format_age, parse_age, JournalEntry};
+use crate::log::log_warn;
+use crate::task::{self, CliTaskConfig, TaskDescriptor, TaskStatus};
+use crate::traversal::Traversal;
+use super::entry::executor_options;
+use super::output::{self, OutputFormat};
+
+const LEGEND: &str = "\
+Flags: [repository] [work]
+  repository: B bare, H HEAD on a branch, E branch exists,
+              0/1/M local branches, 0/1/M remote branches, U has upstream
+  work:       W unsaved workdir, I unsaved index, A ahead, B behind (red when diverged),
+              S submodules (red when dirty)
+  `/` could not be determined
+Sources not backed up within `--stale-after` are marked stale.";
+
+pub fn status_cli() -> Command {
+    Command::new("status")
+        .about("Report the state of the git repositories under a directory, without pushing")
+        .arg(
+            Arg::new("directory")
+                .short('d')
+                .long("directory")
+                .value_name("DIR")
+                .help("Sets the root directory to traverse")
+                .action(ArgAction::Set)
+                .required(true)
+        )
+        .arg(
+            Arg::new("dirty")
+                .long("dirty")
+                .help("Only list repositories with unsaved work")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("unpushed")
+                .long("unpushed")
+                .help("Only list repositories ahead of their upstream")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no-upstream")
+                .long("no-upstream")
+                .help("Only list repositories whose branch has no upstream")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("stale")
+                .long("stale")
+                .help("Only list repositories not backed up within `--stale-after`")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(stale_after_arg())
+        .arg(
+            Arg::new("search-hidden")
+                .short('H')
+                .long("search-hidden")
+                .help("Go into directories whose name begins with `.`")
+                .action(ArgAction::SetTrue)
+        )
+        .after_help("With several filters, repositories matching any of them are listed.")
+}
+
+pub fn stale_after_arg() -> Arg {
+    Arg::new("stale-after")
+        .long("stale-after")
+        .value_name("AGE")
+        .value_parser(|age: &str| parse_age(age).ok_or("expected e.g. `12h`, `7d` or `2w`"))
+        .default_value("7d")
+        .help("How long since the last successful backup until a source is stale")
+        .action(ArgAction::Set)
+}
+
+/// Which rows `--dirty`, `--unpushed`, `--no-upstream` and `--stale` keep.
+struct StatusFilter {
+    dirty: bool,
+    unpushed: bool,
+    no_upstream: bool,
+    stale: bool,
+}
+
+impl StatusFilter {
+    fn accepts(&self, status: &GitRepoStatus, stale: bool) -> bool {
+        if !(self.dirty || self.unpushed || self.no_upstream || self.stale) {
+            return true;
+        }
+        (self.dirty && status.is_dirty())
+            || (self.unpushed && status.is_unpushed())
+            || (self.no_upstream && !status.has_upstream())
+            || (self.stale && stale)
+    }
+}
+
+pub async fn status_main(matches: &ArgMatches) {
+    let dir = matches.get_one::<String>("directory").unwrap();
+    let root_dir = absolute(PathBuf::from(dir)).unwrap();
+    let cli_config = CliTaskConfig {
+        search_hidden: matches.get_flag("search-hidden"),
+        exclude_patterns: Vec::new(),
+        root_dir: root_dir.clone(),
+    };
+    let filter = StatusFilter {
+        dirty: matches.get_flag("dirty"),
+        unpushed: matches.get_flag("unpushed"),
+        no_upstream: matches.get_flag("no-upstream"),
+        stale: matches.get_flag("stale"),
+    };
+    let stale_after = *matches.get_one::<chrono::TimeDelta>("stale-after").unwrap();
+
+    let traversal = Traversal::new(executor_options(matches).threads);
+    traversal.cancel_on_ctrl_c();
+    let Ok(tasks) = task::collect_all_tasks("git", cli_config, &traversal).await else {
+        eprintln!("Interrupted while collecting repositories.");
+        std::process::exit(130);
+    };
+    for err in traversal.errors() {
+        log_warn!("Skipped {}", err);
+    }
+
+    let mut descriptors: Vec<TaskDescriptor> = tasks.iter()
+        .filter(|task| task.kind() == "git")
+        .map(|task| task.describe())
+        .collect();
+    descriptors.sort_by(|a, b| a.source.cmp(&b.source));
+    descriptors.dedup_by(|a, b| a.source == b.source);
+    for descriptor in descriptors.iter_mut() {
+        descriptor.stale = Some(JournalEntry::is_stale(descriptor.journal.as_ref(), stale_after));
+    }
+    // repositories whose status could not be read are always listed
+    descriptors.retain(|descriptor| match &descriptor.status {
+        Some(TaskStatus::Git(status)) => filter.accepts(status, descriptor.stale == Some(true)),
+        _ => true,
+    });
+
+    let format = OutputFormat::from_matches(matches);
+    if format != OutputFormat::Text {
+        output::emit(format, &descriptors, None);
+        return;
+    }
+
+    let rows: Vec<StatusRow> = descriptors.iter().map(|descriptor| {
+        let path = display_path(&descriptor.source, &root_dir);
+        match &descriptor.status {
+            Some(TaskStatus::Git(status)) => status_row(path, status, descriptor),
+            _ => StatusRow {
+                path,
+                branch: "?".to_string(),
+                flags: "?".to_string(),
+                upstream: git_repo_status(&descriptor.source).err().map(|err| format!("error: {}", err)).unwrap_or_default(),
+                age: String::new(),
+                backup: String::new(),
+            },
+        }
+    }).collect();
+    print_table(&rows);
+    println!("\n{}", LEGEND);
+}
+
+struct StatusRow {
+    path: String,
+    branch: String,
+    /// Colored, see `visible_width`.
+    flags: String,
+    upstream: String,
+    age: String,
+    /// Last successful backup, colored when stale.
+    backup: String,
+}
+
+fn status_row(path: String, status: &GitRepoStatus, descriptor: &TaskDescriptor) -> StatusRow {
+    let branch = match &status.head {
+        HeadState::Branch(_) => status.head.branch().to_string(),
+        HeadState::Unborn(_) => format!("{} (unborn)", status.head.branch()),
+        HeadState::Detached(oid) => format!("({:.7})", oid),
+    };
+    let upstream = match status.upstream_counts {
+        Some((ahead, behind)) => format!("+{} -{}", ahead, behind),
+        None => "-".to_string(),
+    };
+    let age = status.last_commit
+        .map(|time| format_age(chrono::Local::now().signed_duration_since(time)))
+        .unwrap_or_else(|| "-".to_string());
+    let backup = match descriptor.journal.as_ref().and_then(JournalEntry::age) {
+        Some(age) => format!("{} ago", format_age(age)),
+        None => "never".to_string(),
+    };
+    let backup = if descriptor.stale == Some(true) {
+        format!("{} (stale)", backup).red().to_string()
+    } else {
+        backup
+    };
+    StatusRow {
+        path,
+        branch,
+        flags: format!("[{}] [{}]", status.repo_check.to_string(), status.work_status.to_string()),
+        upstream,
+        age,
+        backup,
+    }
+}
+
+fn display_path(repo_path: &Path, root_dir: &Path) -> String {
+    match repo_path.strip_prefix(root_dir) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => repo_path.display().to_string(),
+    }
+}
+
+/// The printed width of `text`, leaving out the escape sequences of its colors.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ ... m`
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+fn print_table(rows: &[StatusRow]) {
+    let header = ["PATH", "BRANCH", "FLAGS", "UPSTREAM", "LAST COMMIT", "LAST BACKUP"];
+    let path_width = rows.iter().map(|row| row.path.chars().count()).chain([header[0].len()]).max().unwrap();
+    let branch_width = rows.iter().map(|row| row.branch.chars().count()).chain([header[1].len()]).max().unwrap();
+    let flags_width = rows.iter().map(|row| visible_width(&row.flags)).chain([header[2].len()]).max().unwrap();
+    let upstream_width = rows.iter().map(|row| row.upstream.chars().count()).chain([header[3].len()]).max().unwrap();
+    let age_width = rows.iter().map(|row| row.age.chars().count()).chain([header[4].len()]).max().unwrap();
+
+    println!(
+        "{:<path_width$}  {:<branch_width$}  {:<flags_width$}  {:<upstream_width$}  {:<age_width$}  {}",
+        header[0], header[1], header[2], header[3], header[4], header[5],
+    );
+    for row in rows {
+        // padded by hand, the color codes of the flags do not take up space
+        let flags_padding = " ".repeat(flags_width - visible_width(&row.flags));
+        println!(
+            "{:<path_width$}  {:<branch_width$}  {}{}  {:<upstream_width$}  {:<age_width$}  {}",
+            row.path, row.branch, row.flags, flags_padding, row.upstream, row.age, row.backup,
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use tokio::sync::{watch, Mutex, Semaphore};
+
+use crate::log::task_span;
+use crate::task::{PushTask, TaskError, TaskList, TaskOutcome};
+
+/// Concurrent tasks of a kind unless `--limit` says otherwise.
+const DEFAULT_KIND_LIMITS: [(&str, usize); 1] = [("git", 4)];
+
+/// How many tasks `run_tasks` runs at the same time, from `--threads` and `--limit`.
+#[derive(Debug, Clone)]
+pub struct ExecutorOptions {
+    pub threads: usize,
+    /// Per `PushTask::kind`, below `threads`.
+    pub kind_limits: HashMap<String, usize>,
+}
+
+impl Default for ExecutorOptions {
+    fn default() -> Self {
+        ExecutorOptions {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            kind_limits: DEFAULT_KIND_LIMITS.iter().map(|(kind, limit)| (kind.to_string(), *limit)).collect(),
+        }
+    }
+}
+
+/// A task after `run_tasks`, with what it did.
+pub struct TaskRun {
+    pub task: Box<dyn PushTask + Send>,
+    pub result: Result<TaskOutcome, TaskError>,
+    /// The commands of a dry run.
+    pub commands: Option<Vec<String>>,
+    pub started: DateTime<Local>,
+    pub duration: Duration,
+}
+
+/// The tasks `tasks[index]` waits for: those on directories inside its source, and those on
+/// the same source listed before it, e.g. the git autosave of a repository before the borg
+/// archive containing it.
+fn dependencies(tasks: &TaskList, index: usize) -> Vec<usize> {
+    let source = tasks[index].source();
+    tasks.iter()
+        .enumerate()
+        .filter(|(other, task)| {
+            let other_source = task.source();
+            (other_source != source && other_source.starts_with(source))
+                || (other_source == source && *other < index)
+        })
+        .map(|(other, _)| other)
+        .collect()
+}
+
+/// Run `tasks` on the blocking pool, at most `options.threads` at a time, keeping
+/// dependencies in order and tasks sharing a `PushTask::exclusive_resource` apart. Dry runs
+/// collect the commands of each task on their own, so that they can be shown in order.
+/// Returns the runs in the order of `tasks`.
+pub async fn run_tasks(tasks: TaskList, dry_run: bool, options: &ExecutorOptions) -> Vec<TaskRun> {
+    let threads = Arc::new(Semaphore::new(options.threads.max(1)));
+    let mut kind_limits: HashMap<&'static str, Arc<Semaphore>> = HashMap::new();
+    let mut resources: HashMap<String, Arc<Mutex<()>>> = HashMap::new();
+    let (done_senders, done_receivers): (Vec<_>, Vec<_>) = tasks.iter().map(|_| watch::channel(false)).unzip();
+    let all_dependencies: Vec<Vec<usize>> = (0..tasks.len()).map(|index| dependencies(&tasks, index)).collect();
+
+    let mut handles = Vec::new();
+    for ((task, done), task_dependencies) in tasks.into_iter().zip(done_senders).zip(all_dependencies) {
+        let waits_for: Vec<watch::Receiver<bool>> = task_dependencies.iter().map(|&i| done_receivers[i].clone()).collect();
+        let kind_limit = options.kind_limits.get(task.kind()).map(|&limit| {
+            kind_limits.entry(task.kind()).or_insert_with(|| Arc::new(Semaphore::new(limit.max(1)))).clone()
+        });
+        let resource = task.exclusive_resource().map(|resource| {
+            resources.entry(resource).or_insert_with(|| Arc::new(Mutex::new(()))).clone()
+        });
+        let threads = threads.clone();
+
+        handles.push(tokio::spawn(async move {
+            for mut receiver in waits_for {
+                // a dropped sender means the dependency is gone as well
+                let _ = receiver.wait_for(|done| *done).await;
+            }
+            // always taken in this order, so that no two tasks wait for each other
+            let _resource = match &resource {
+                Some(resource) => Some(resource.lock().await),
+                None => None,
+            };
+            let _kind_permit = match &kind_limit {
+                Some(limit) => Some(limit.acquire().await.expect("semaphore is never closed")),
+                None => None,
+            };
+            let _permit = threads.acquire().await.expect("semaphore is never closed");
+
+            let run = tokio::task::spawn_blocking(move || {
+                let mut commands = dry_run.then(Vec::new);
+                let started = Local::now();
+                let timer = std::time::Instant::now();
+                let result = task_span(task.as_ref()).in_scope(|| task.execute(&mut commands));
+                TaskRun { task, result, commands, started, duration: timer.elapsed() }
+            }).await;
+            let _ = done.send(true);
+            run
+        }));
+    }
+
+    let mut runs = Vec::new();
+    for handle in handles {
+        match handle.await {
+            Ok(Ok(run)) => runs.push(run),
+            Ok(Err(err)) | Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Sleeps a bit, counting how many run at once and noting when it ran.
+    #[derive(Debug)]
+    struct ProbeTask {
+        source: PathBuf,
+        kind: &'static str,
+        resource: Option<String>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        finished: Arc<std::sync::Mutex<Vec<(PathBuf, &'static str)>>>,
+    }
+
+    impl PushTask for ProbeTask {
+        fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
+            let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(30));
+            if let Some(list) = command_list {
+                list.push(format!("{}", self.source.display()));
+            }
+            self.finished.lock().unwrap().push((self.source.clone(), self.kind));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(TaskOutcome::Succeeded)
+        }
+        fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+            Ok(Vec::new())
+        }
+        fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            Ok(())
+        }
+        fn kind(&self) -> &'static str {
+            self.kind
+        }
+        fn source(&self) -> &Path {
+            &self.source
+        }
+        fn exclusive_resource(&self) -> Option<String> {
+            self.resource.clone()
+        }
+    }
+
+    struct Probe {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        finished: Arc<std::sync::Mutex<Vec<(PathBuf, &'static str)>>>,
+    }
+
+    impl Probe {
+        fn new() -> Self {
+            Probe {
+                running: Arc::new(AtomicUsize::new(0)),
+                max_running: Arc::new(AtomicUsize::new(0)),
+                finished: Arc::new(std::sync::Mutex::new(Vec::new())),
+            }
+        }
+
+        fn task(&self, source: &str, kind: &'static str, resource: Option<&str>) -> Box<dyn PushTask + Send> {
+            Box::new(ProbeTask {
+                source: PathBuf::from(source),
+                kind,
+                resource: resource.map(str::to_string),
+                running: self.running.clone(),
+                max_running: self.max_running.clone(),
+                finished: self.finished.clone(),
+            })
+        }
+    }
+
+    fn options(threads: usize, kind_limits: &[(&str, usize)]) -> ExecutorOptions {
+        ExecutorOptions {
+            threads,
+            kind_limits: kind_limits.iter().map(|(kind, limit)| (kind.to_string(), *limit)).collect(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_limits() {
+        let probe = Probe::new();
+        let tasks: TaskList = (0..6).map(|i| probe.task(&format!("/src/{}", i), "git", None)).collect();
+        let runs = run_tasks(tasks, true, &options(3, &[])).await;
+        assert_eq!(probe.max_running.load(Ordering::SeqCst), 3);
+        // commands in the order of the tasks, whatever order they ran in
+        let commands: Vec<String> = runs.into_iter().flat_map(|run| run.commands.unwrap()).collect();
+        assert_eq!(commands, (0..6).map(|i| format!("/src/{}", i)).collect::<Vec<_>>());
+
+        let probe = Probe::new();
+        let tasks: TaskList = (0..6).map(|i| probe.task(&format!("/src/{}", i), "git", None)).collect();
+        run_tasks(tasks, true, &options(8, &[("git", 2)])).await;
+        assert_eq!(probe.max_running.load(Ordering::SeqCst), 2);
+
+        let probe = Probe::new();
+        let tasks: TaskList = (0..4).map(|i| probe.task(&format!("/src/{}", i), "borg", Some("borg:/repo"))).collect();
+        run_tasks(tasks, true, &options(8, &[])).await;
+        assert_eq!(probe.max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dependencies_run_first() {
+        let probe = Probe::new();
+        let tasks: TaskList = vec![
+            probe.task("/src", "borg", None),
+            probe.task("/src/a", "git", None),
+            probe.task("/src/a", "borg", None),
+            probe.task("/src/b", "git", None),
+            probe.task("/srcother", "git", None),
+        ];
+        run_tasks(tasks, false, &options(8, &[])).await;
+        let finished = probe.finished.lock().unwrap().clone();
+        let position = |source: &str, kind: &str| {
+            finished.iter().position(|(s, k)| s == Path::new(source) && *k == kind).unwrap()
+        };
+        assert_eq!(finished.len(), 5);
+        assert_eq!(position("/src", "borg"), 4);
+        assert!(position("/src/a", "git") < position("/src/a", "borg"));
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let probe = Probe::new();
+        let tasks: TaskList = vec![
+            probe.task("/src", "borg", None),
+            probe.task("/src/a", "git", None),
+            probe.task("/src/a", "borg", None),
+            probe.task("/srcother", "git", None),
+        ];
+        assert_eq!(dependencies(&tasks, 0), vec![1, 2]);
+        assert_eq!(dependencies(&tasks, 1), Vec::<usize>::new());
+        assert_eq!(dependencies(&tasks, 2), vec![1]);
+        assert_eq!(dependencies(&tasks, 3), Vec::<usize>::new());
+    }
+}
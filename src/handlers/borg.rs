@@ -1,10 +1,10 @@
-use std::{path::PathBuf, process::Command};
+use std::{fmt, path::{Path, PathBuf}, process::Command};
 use bevy_reflect::Reflect;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{log::{log, LogLevel}, task::PushTask};
+use crate::{journal::{state_dir, state_file_name, write_state_file, JournalEntry}, log::{log_error, log_info, log_warn}, task::{PushTask, TaskDescriptor, TaskError, TaskOutcome, TaskStatus}};
 
-use super::{exclude::BorgPattern, toml_config::{CompletableConfig, HasInheritableConfig, InheritableConfig, OnRecursion}};
+use super::{common::CommandError, exclude::BorgPattern, template::TemplateContext, toml_config::{check_fields, inherit_on_recursion, CompletableConfig, HasInheritableConfig, InheritableConfig, OnRecursion}};
 
 // *************************************************************************** //
 // Configuration Types and Implementations
@@ -13,20 +13,98 @@ use super::{exclude::BorgPattern, toml_config::{CompletableConfig, HasInheritabl
 #[derive(Debug, Deserialize, Clone, Reflect)]
 pub struct BorgTargetConfig {
     pub mode: Option<String>,
+    /// The repository. A legacy `repo::archive` form is still accepted, its archive part
+    /// being used as the template when no `archive` is configured.
     pub target: Option<String>,
+    /// Run `borg init` when the repository does not exist yet, defaults to `false`.
+    pub auto_init: Option<bool>,
+    /// Encryption mode passed to `borg init`, required by `auto_init`.
+    pub encryption: Option<String>,
+    /// Run `borg check` after every N-th successful create.
+    pub check_every: Option<u32>,
 }
 
+impl BorgTargetConfig {
+    /// Split `target` into the repository and the legacy archive part, if any.
+    pub fn repository_and_archive(&self) -> (String, Option<String>) {
+        let target = self.target.clone().unwrap();
+        match target.split_once("::") {
+            Some((repository, archive)) => (repository.to_string(), Some(archive.to_string())),
+            None => (target, None),
+        }
+    }
+
+    fn repository_check(&self) -> Result<(), &'static str> {
+        if self.encryption.as_deref().is_some_and(|e| !ENCRYPTION_MODES.contains(&e)) {
+            return Err("Unknown borg encryption mode");
+        }
+        if self.auto_init == Some(true) && self.encryption.is_none() {
+            return Err("`auto_init` requires an `encryption` mode");
+        }
+        if self.check_every == Some(0) {
+            return Err("`check_every` must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+pub const ENCRYPTION_MODES: [&str; 7] = [
+    "none", "authenticated", "authenticated-blake2",
+    "repokey", "keyfile", "repokey-blake2", "keyfile-blake2",
+];
+
+pub const DEFAULT_ARCHIVE_TEMPLATE: &str = "{hostname}-{path}-{now:%Y-%m-%dT%H:%M:%S}";
+
 #[derive(Debug, Deserialize, Clone, Reflect)]
 pub struct BorgConfig {
     pub target: Option<BorgTargetConfig>,
     pub assets: Option<BorgInheritableConfig>,
     pub heritage: Option<BorgInheritableConfig>,
+    pub retention: Option<BorgRetentionConfig>,
+}
+
+/// Archives to keep when pruning after a successful `borg create`.
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct BorgRetentionConfig {
+    pub keep_within: Option<String>,
+    pub keep_daily: Option<u32>,
+    pub keep_weekly: Option<u32>,
+    pub keep_monthly: Option<u32>,
+    pub keep_yearly: Option<u32>,
+    /// Only archives matching this glob are pruned. It is a template like `archive`;
+    /// by default the archive template with its date and time placeholders replaced by `*`.
+    pub glob_archives: Option<String>,
+    /// Run `borg compact` after pruning, defaults to `true`.
+    pub compact: Option<bool>,
+}
+
+impl BorgRetentionConfig {
+    fn keep_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(within) = &self.keep_within {
+            args.push("--keep-within".to_string());
+            args.push(within.clone());
+        }
+        for (flag, count) in [
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ] {
+            if let Some(count) = count {
+                args.push(flag.to_string());
+                args.push(count.to_string());
+            }
+        }
+        args
+    }
 }
 
 impl Default for BorgConfig {
     fn default() -> Self {
         BorgConfig {
             target: None,
+            retention: None,
             assets: Some(
                 BorgInheritableConfig {
                     trigger_by: vec!["borg".to_string()].into(),
@@ -34,6 +112,13 @@ impl Default for BorgConfig {
                     extra_exclude_mode: vec!["git".to_string()].into(),
                     on_recursion: Some(OnRecursion::Inherit),
                     ignore_child: None,
+                    acl: None,
+                    xattrs: None,
+                    numeric_owner: None,
+                    one_file_system: None,
+                    compression: None,
+                    chunker_params: None,
+                    archive: None,
                 }
             ),
             heritage: Some(
@@ -43,6 +128,13 @@ impl Default for BorgConfig {
                     extra_exclude_mode: None,
                     on_recursion: Some(OnRecursion::Inherit),
                     ignore_child: Some(false),
+                    acl: None,
+                    xattrs: None,
+                    numeric_owner: None,
+                    one_file_system: None,
+                    compression: None,
+                    chunker_params: None,
+                    archive: None,
                 }
             ),
         }
@@ -86,48 +178,55 @@ pub struct BorgInheritableConfig {
     pub extra_exclude_mode: Option<Vec<String>>,
     pub on_recursion: Option<OnRecursion>,
     pub ignore_child: Option<bool>,
+    // Options of `borg create`. Unset ones are inherited, then fall back to `BorgCreateOptions::default()`.
+    pub acl: Option<bool>,
+    pub xattrs: Option<bool>,
+    pub numeric_owner: Option<bool>,
+    pub one_file_system: Option<bool>,
+    pub compression: Option<String>,
+    pub chunker_params: Option<String>,
+    /// Archive name template, see `TemplateContext` for placeholders.
+    pub archive: Option<String>,
 }
 
 impl InheritableConfig for BorgInheritableConfig {
     fn inherit_from(&self, super_config: Option<&Self>) -> Self {
         let mut this = self.clone();
-        match self.on_recursion {
-            Some(OnRecursion::Inherit) => {
-                match super_config {
-                    Some(super_config) => {
-                        this.on_recursion = super_config.on_recursion.clone();
-                    },
-                    None => {
-                        this.on_recursion = Some(OnRecursion::default());
-                    }
-                }
-            },
-            None => unreachable!(),
-            _ => {}
+        this.on_recursion = inherit_on_recursion(&self.on_recursion, super_config.map(|c| &c.on_recursion));
+        if let Some(super_config) = super_config {
+            if this.acl.is_none() {
+                this.acl = super_config.acl;
+            }
+            if this.xattrs.is_none() {
+                this.xattrs = super_config.xattrs;
+            }
+            if this.numeric_owner.is_none() {
+                this.numeric_owner = super_config.numeric_owner;
+            }
+            if this.one_file_system.is_none() {
+                this.one_file_system = super_config.one_file_system;
+            }
+            if this.compression.is_none() {
+                this.compression = super_config.compression.clone();
+            }
+            if this.chunker_params.is_none() {
+                this.chunker_params = super_config.chunker_params.clone();
+            }
+            if this.archive.is_none() {
+                this.archive = super_config.archive.clone();
+            }
         }
         this
     }
 }
 
-macro_rules! check_fields {
-    ($obj:expr, $($field:ident),+) => {
-        {
-            let mut complete = true;
-            $(
-                if $obj.$field.is_none() {
-                    complete = false;
-                }
-            )+
-            complete
-        }
-    };
-}
-
 impl CompletableConfig for BorgConfig {
     type CompletionResult = Result<Self, &'static str>;
 
     fn is_complete(&self) -> bool {
-        // todo: check target
+        if self.target.as_ref().is_some_and(|t| t.repository_check().is_err()) {
+            return false;
+        }
         if let Some(as_child) = &self.assets {
             if !check_fields!(as_child, trigger_by, on_recursion) {
                 return false;
@@ -137,7 +236,7 @@ impl CompletableConfig for BorgConfig {
         }
         if let Some(as_super) = &self.heritage {
             if as_super.exclude_list.is_some() {
-                log(LogLevel::Warn, "Exclude list as super has no effect yet.");
+                log_warn!("Exclude list as super has no effect yet.");
             }
             if !check_fields!(as_super, ignore_child, on_recursion) {
                 return false;
@@ -145,7 +244,14 @@ impl CompletableConfig for BorgConfig {
         } else {
             return false;
         }
-        true
+        if self.retention.as_ref().is_some_and(|r| r.keep_args().is_empty()) {
+            return false;
+        }
+        // leave invalid create options to `completion`, which reports them
+        [&self.assets, &self.heritage].into_iter().flatten().all(|inheritable| {
+            inheritable.compression.as_deref().is_none_or(|c| validate_compression(c).is_ok())
+                && inheritable.archive.as_deref().is_none_or(|a| TemplateContext::check(a).is_ok())
+        })
     }
 
     fn completion(&self) -> Self::CompletionResult {
@@ -160,10 +266,31 @@ impl CompletableConfig for BorgConfig {
             if !["path"].contains(&target.mode.as_ref().unwrap().as_str()) {
                 return Err("Invalid target mode");
             }
+            target.repository_check()?;
         } else {
             return Err("Target config is required");
         }
 
+        // check retention
+        if let Some(retention) = &self.retention {
+            if retention.keep_args().is_empty() {
+                return Err("Retention requires at least one `keep_*` rule");
+            }
+            if let Some(glob) = &retention.glob_archives {
+                TemplateContext::check(glob).map_err(|_| "Invalid `glob_archives` template")?;
+            }
+        }
+
+        // check create options
+        for inheritable in [&self.assets, &self.heritage].into_iter().flatten() {
+            if let Some(compression) = &inheritable.compression {
+                validate_compression(compression)?;
+            }
+            if let Some(archive) = &inheritable.archive {
+                TemplateContext::check(archive).map_err(|_| "Invalid archive name template")?;
+            }
+        }
+
         // complete as_child
         if let Some(as_child) = &mut result.assets {
             if as_child.trigger_by.is_none() {
@@ -199,13 +326,227 @@ impl CompletableConfig for BorgConfig {
 #[derive(Debug)]
 pub struct BorgCreateTask {
 	pub source: PathBuf,
-	pub target: String,
+	pub repository: String,
+	/// Expanded archive name.
+	pub archive: String,
 	pub exclude_list: Vec<PathBuf>,
     pub extra_exclude_patterns: Vec<BorgPattern>, // 新增字段
-	pub options: BorgCreateOptions
+	pub options: BorgCreateOptions,
+	pub prune: Option<BorgPruneOptions>,
+	pub repository_options: BorgRepositoryOptions,
+}
+
+/// Pre-flight and health checks of the repository.
+#[derive(Debug, Default)]
+pub struct BorgRepositoryOptions {
+    /// Encryption mode to `borg init` a missing repository with, `None` to fail instead.
+    pub init_encryption: Option<String>,
+    pub check_every: Option<u32>,
+}
+
+/// Prune (and compact) run after a successful create.
+#[derive(Debug, Clone, Serialize)]
+pub struct BorgPruneOptions {
+    /// Expanded glob selecting the archives subject to pruning.
+    pub glob_archives: String,
+    pub keep_args: Vec<String>,
+    pub compact: bool,
+}
+
+/// What `--format json` reports about a borg task besides its target.
+#[derive(Debug, Serialize)]
+pub struct BorgTaskStatus {
+    pub prune: Option<BorgPruneOptions>,
+    pub check_every: Option<u32>,
+    /// Only loaded with `check_every`.
+    pub check_state: Option<BorgCheckState>,
 }
 
 impl BorgCreateTask {
+    /// Build the task from a completed and inherited config, expanding the archive name
+    /// template now so that preview and execution agree on it.
+    pub fn new(
+        source: PathBuf,
+        root: &Path,
+        config: &BorgConfig,
+        exclude_list: Vec<PathBuf>,
+        extra_exclude_patterns: Vec<BorgPattern>,
+    ) -> Result<Self, String> {
+        let context = TemplateContext::for_source(&source, root);
+        BorgCreateTask::with_context(source, &context, config, exclude_list, extra_exclude_patterns)
+    }
+
+    /// As `new`, with the archive name expanded in `context`.
+    fn with_context(
+        source: PathBuf,
+        context: &TemplateContext,
+        config: &BorgConfig,
+        exclude_list: Vec<PathBuf>,
+        extra_exclude_patterns: Vec<BorgPattern>,
+    ) -> Result<Self, String> {
+        let assets = config.get_assets_config();
+        let target = config.target.as_ref().unwrap();
+        let (repository, legacy_archive) = target.repository_and_archive();
+        let template = assets.archive.clone()
+            .or(legacy_archive)
+            .unwrap_or_else(|| DEFAULT_ARCHIVE_TEMPLATE.to_string());
+        let archive = context.expand(&template)?;
+        if archive.is_empty() || archive.contains('/') {
+            return Err(format!("Invalid borg archive name `{}`", archive));
+        }
+        let prune = match &config.retention {
+            Some(retention) => Some(BorgPruneOptions {
+                glob_archives: match &retention.glob_archives {
+                    Some(glob) => context.expand(glob)?,
+                    None => context.expand_glob(&template)?,
+                },
+                keep_args: retention.keep_args(),
+                compact: retention.compact.unwrap_or(true),
+            }),
+            None => None,
+        };
+        Ok(BorgCreateTask {
+            source,
+            repository,
+            archive,
+            exclude_list,
+            extra_exclude_patterns,
+            options: BorgCreateOptions::from_config(assets),
+            prune,
+            repository_options: BorgRepositoryOptions {
+                init_encryption: target.encryption.clone().filter(|_| target.auto_init == Some(true)),
+                check_every: target.check_every,
+            },
+        })
+    }
+
+    fn create_command(&self) -> Result<Command, BorgError> {
+        let mut command = Command::new("borg");
+        command
+            .arg("create")
+            .arg("--stats")
+            .arg("--progress");
+        command.args(self.options.to_args());
+
+        command.args(self.exclude_options()?);
+
+        command
+            .arg(self.location())
+            .arg(&self.source);
+        Ok(command)
+    }
+
+    /// `borg prune` and `borg compact` to run after the create, if retention is configured.
+    fn prune_commands(&self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        if let Some(prune) = &self.prune {
+            let mut command = Command::new("borg");
+            command
+                .arg("prune")
+                .arg("--list")
+                .arg("--glob-archives")
+                .arg(&prune.glob_archives)
+                .args(&prune.keep_args)
+                .arg(&self.repository);
+            commands.push(command);
+            if prune.compact {
+                let mut command = Command::new("borg");
+                command
+                    .arg("compact")
+                    .arg(&self.repository);
+                commands.push(command);
+            }
+        }
+        commands
+    }
+
+    /// Make sure the repository exists before creating archives in it, running `borg init`
+    /// when it is missing and `auto_init` is enabled.
+    fn ensure_repository(&self) -> Result<(), BorgError> {
+        let output = Command::new("borg")
+            .arg("info")
+            .arg(&self.repository)
+            .output()
+            .map_err(BorgError::Io)?;
+        if matches!(output.status.code(), Some(0) | Some(1)) {
+            return Ok(());
+        }
+        let stderr_cow = String::from_utf8_lossy(&output.stderr);
+        if !repository_missing(output.status.code(), &stderr_cow) {
+            log_error!("Failed to query borg repository: {}", stderr_cow);
+            return Err(BorgError::Command(CommandError::from_output("borg", &output)));
+        }
+        match &self.repository_options.init_encryption {
+            Some(encryption) => {
+                log_info!("Initialising borg repository {}", self.repository);
+                let mut command = Command::new("borg");
+                command
+                    .arg("init")
+                    .arg("--encryption")
+                    .arg(encryption)
+                    .arg(&self.repository);
+                run_borg(command, "initialise borg repository")
+            },
+            None => Err(BorgError::MissingRepository(self.repository.clone())),
+        }
+    }
+
+    fn check_command(&self) -> Command {
+        let mut command = Command::new("borg");
+        command
+            .arg("check")
+            .arg(&self.repository);
+        command
+    }
+
+    /// Whether the run about to happen is the one to `borg check` after.
+    fn check_due(&self, state: &BorgCheckState) -> bool {
+        self.repository_options.check_every
+            .is_some_and(|every| state.runs_since_check + 1 >= every)
+    }
+
+    /// Count this run and `borg check` the repository if it is due, recording the result.
+    fn check_if_due(&self) -> Result<(), BorgError> {
+        if self.repository_options.check_every.is_none() {
+            return Ok(());
+        }
+        let mut state = BorgCheckState::load(&self.repository);
+        if !self.check_due(&state) {
+            state.runs_since_check += 1;
+            return state.save(&self.repository).map_err(BorgError::Io);
+        }
+        let result = run_borg(self.check_command(), "check borg repository");
+        state.runs_since_check = 0;
+        state.last_check = Some(chrono::Local::now().to_rfc3339());
+        state.last_check_ok = Some(result.is_ok());
+        state.save(&self.repository).map_err(BorgError::Io)?;
+        result
+    }
+
+    /// The `repo::archive` location passed to borg.
+    pub fn location(&self) -> String {
+        format!("{}::{}", self.repository, self.archive)
+    }
+
+    /// `--exclude` options for `borg create`.
+    fn exclude_options(&self) -> Result<Vec<String>, BorgError> {
+        let mut vec: Vec<String> = Vec::new();
+        let values = self
+            .borg_exclude_patterns()
+            .map_err(BorgError::Pattern)?
+            .into_iter()
+            .map(|p| {
+                // println!("{:?}", p);
+                format!("{}", p)
+            });
+        values.for_each(|val| {
+            vec.push("--exclude".to_string());
+            vec.push(val);
+        });
+        Ok(vec)
+        // maybe use a temp file if too long.
+    }
+
     fn borg_exclude_patterns(&self) -> Result<Vec<BorgPattern>, &'static str> {
         let mut patterns = Vec::new();
         
@@ -229,46 +570,32 @@ impl BorgCreateTask {
 }
 
 impl PushTask for BorgCreateTask {
-    fn execute(&self, command_list: &mut Option<Vec<String>>) {
-        let mut command = Command::new("borg");
-        command
-            .arg("create")
-            .arg("--stats")
-            .arg("--progress")
-            .arg("--one-file-system")
-            .arg("--compression")
-            .arg(&self.options.compression);
-
-        command.args(self.exclude_pattern_options());
-
-        command
-            .arg(&self.target)
-            .arg(&self.source);
+    fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
+        let command = self.create_command()?;
 
         if let Some(command_list) = command_list {
             command_list.push(format!("{:?}", command));
-            return;
+            for command in self.prune_commands() {
+                command_list.push(format!("{:?}", command));
+            }
+            if self.check_due(&BorgCheckState::load(&self.repository)) {
+                command_list.push(format!("{:?}", self.check_command()));
+            }
+            Ok(TaskOutcome::Succeeded)
         } else {
-            command.output().expect("failed to execute process");
+            self.ensure_repository()?;
+            run_borg(command, "create borg archive")?;
+            // only reached when the archive was created
+            for command in self.prune_commands() {
+                run_borg(command, "prune borg repository")?;
+            }
+            self.check_if_due()?;
+            Ok(TaskOutcome::Succeeded)
         }
     }
 
-    fn exclude_pattern_options(&self) -> Vec<String> {
-        let mut vec: Vec<String> = Vec::new();
-        let values = self
-            .borg_exclude_patterns()
-            .unwrap()
-            .into_iter()
-            .map(|p| {
-                // println!("{:?}", p);
-                format!("{}", p)
-            });
-        values.for_each(|val| {
-            vec.push("--exclude".to_string());
-            vec.push(val);
-        });
-        vec
-        // maybe use a temp file if too long.
+    fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+        Ok(self.exclude_options()?)
     }
 
     fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -294,29 +621,410 @@ impl PushTask for BorgCreateTask {
         println!(
             "Borg archive: [{}] -> [{}]", 
             self.source.canonicalize()?.display(),
-            self.target
+            self.location()
         );
+        if let Some(prune) = &self.prune {
+            println!(
+                "  then prune [{}] {} {}",
+                prune.glob_archives,
+                prune.keep_args.join(" "),
+                if prune.compact { "and compact" } else { "" }
+            );
+        }
+        if let Some(every) = self.repository_options.check_every {
+            let state = BorgCheckState::load(&self.repository);
+            println!(
+                "  check every {} runs, next after {} more{}",
+                every,
+                every.saturating_sub(state.runs_since_check),
+                match (&state.last_check, state.last_check_ok) {
+                    (Some(time), Some(ok)) => format!(", last check {} at {}", if ok { "passed" } else { "FAILED" }, time),
+                    _ => String::new(),
+                }
+            );
+        }
         Ok(())
     }
+
+    fn kind(&self) -> &'static str {
+        "borg"
+    }
+
+    fn source(&self) -> &Path {
+        &self.source
+    }
+
+    fn target(&self) -> Option<String> {
+        Some(self.location())
+    }
+
+    fn exclusive_resource(&self) -> Option<String> {
+        Some(format!("borg:{}", self.repository))
+    }
+
+    fn journal(&self, entry: &mut JournalEntry) {
+        entry.archive = Some(self.archive.clone());
+    }
+
+    fn describe(&self) -> TaskDescriptor {
+        let mut descriptor = TaskDescriptor::new(self);
+        let check_every = self.repository_options.check_every;
+        descriptor.status = Some(TaskStatus::Borg(BorgTaskStatus {
+            prune: self.prune.clone(),
+            check_every,
+            check_state: check_every.map(|_| BorgCheckState::load(&self.repository)),
+        }));
+        descriptor
+    }
+}
+
+/// Run a borg command. Exit code 1 means warnings (e.g. a file changed while being read)
+/// and still counts as success.
+fn run_borg(mut command: Command, action: &str) -> Result<(), BorgError> {
+    let output = command.output().map_err(BorgError::Io)?;
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => {
+            log_warn!("Finished with warnings to {}: {}", action, String::from_utf8_lossy(&output.stderr));
+            Ok(())
+        },
+        _ => {
+            let stderr_cow = String::from_utf8_lossy(&output.stderr);
+            log_error!("Failed to {}: {}", action, stderr_cow);
+            Err(BorgError::Command(CommandError::from_output("borg", &output)))
+        },
+    }
+}
+
+/// Whether a failed `borg info` means the repository is missing. borg 1.4 has dedicated
+/// exit codes for it, older versions only tell in the message.
+fn repository_missing(code: Option<i32>, stderr: &str) -> bool {
+    matches!(code, Some(13) | Some(15))
+        || stderr.contains("does not exist")
+        || stderr.contains("is not a valid repository")
+}
+
+/// Runs counted towards the next `borg check` of a repository, and the last result,
+/// kept under `$XDG_STATE_HOME/dionysius/borg-check`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BorgCheckState {
+    pub runs_since_check: u32,
+    pub last_check: Option<String>,
+    pub last_check_ok: Option<bool>,
+}
+
+impl BorgCheckState {
+    fn path(repository: &str) -> PathBuf {
+        state_dir().join("borg-check").join(format!("{}.toml", state_file_name(repository)))
+    }
+
+    /// The recorded state, or a fresh one if nothing (readable) was recorded yet.
+    pub fn load(repository: &str) -> Self {
+        std::fs::read_to_string(BorgCheckState::path(repository))
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, repository: &str) -> std::io::Result<()> {
+        write_state_file(&BorgCheckState::path(repository), &toml::to_string(self).map_err(std::io::Error::other)?)
+    }
 }
 
 #[derive(Debug)]
 pub struct BorgCreateOptions {
 	pub acl: bool,
+	pub xattrs: bool,
 	pub numeric_owner: bool,
+	pub one_file_system: bool,
 	pub compression: String,
+	pub chunker_params: Option<String>,
 }
 
 impl Default for BorgCreateOptions {
     fn default() -> Self {
         BorgCreateOptions {
             acl: true,
+            xattrs: true,
             numeric_owner: true,
+            one_file_system: true,
             compression: "zstd".to_string(),
+            chunker_params: None,
+        }
+    }
+}
+
+impl BorgCreateOptions {
+    /// Options from an inherited `[borg.assets]`, defaulting what is still unset.
+    pub fn from_config(config: &BorgInheritableConfig) -> Self {
+        let default = BorgCreateOptions::default();
+        BorgCreateOptions {
+            acl: config.acl.unwrap_or(default.acl),
+            xattrs: config.xattrs.unwrap_or(default.xattrs),
+            numeric_owner: config.numeric_owner.unwrap_or(default.numeric_owner),
+            one_file_system: config.one_file_system.unwrap_or(default.one_file_system),
+            compression: config.compression.clone().unwrap_or(default.compression),
+            chunker_params: config.chunker_params.clone().or(default.chunker_params),
+        }
+    }
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.one_file_system {
+            args.push("--one-file-system".to_string());
+        }
+        if self.numeric_owner {
+            args.push("--numeric-ids".to_string());
+        }
+        if !self.acl {
+            args.push("--noacls".to_string());
         }
+        if !self.xattrs {
+            args.push("--noxattrs".to_string());
+        }
+        args.push("--compression".to_string());
+        args.push(self.compression.clone());
+        if let Some(chunker_params) = &self.chunker_params {
+            args.push("--chunker-params".to_string());
+            args.push(chunker_params.clone());
+        }
+        args
+    }
+}
+
+/// Check a borg compression spec, e.g. `zstd,10`, `auto,lz4` or `obfuscate,110,zstd`.
+pub fn validate_compression(spec: &str) -> Result<(), &'static str> {
+    let mut parts = spec.split(',');
+    match parts.next().unwrap_or_default() {
+        "auto" => {
+            let rest: Vec<&str> = parts.collect();
+            if rest.is_empty() {
+                return Err("`auto` compression requires an algorithm, e.g. `auto,lz4`");
+            }
+            validate_compression(&rest.join(","))
+        },
+        "obfuscate" => {
+            parts.next()
+                .and_then(|level| level.parse::<u32>().ok())
+                .ok_or("`obfuscate` compression requires a numeric spec, e.g. `obfuscate,110,zstd`")?;
+            let rest: Vec<&str> = parts.collect();
+            if rest.is_empty() {
+                return Err("`obfuscate` compression requires an algorithm, e.g. `obfuscate,110,zstd`");
+            }
+            validate_compression(&rest.join(","))
+        },
+        algorithm @ ("none" | "lz4" | "zstd" | "zlib" | "lzma") => {
+            let level = parts.next();
+            if parts.next().is_some() {
+                return Err("Too many fields in compression spec");
+            }
+            let Some(level) = level else { return Ok(()) };
+            let max_level = match algorithm {
+                "zstd" => 22,
+                "zlib" | "lzma" => 9,
+                _ => return Err("This compression algorithm takes no level"),
+            };
+            match level.parse::<u32>() {
+                Ok(level) if (algorithm != "zstd" || level >= 1) && level <= max_level => Ok(()),
+                _ => Err("Compression level out of range"),
+            }
+        },
+        _ => Err("Unknown compression algorithm"),
     }
 }
 
 // *************************************************************************** //
 // Error Types
-// *************************************************************************** //
\ No newline at end of file
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub enum BorgError {
+    Io(std::io::Error),
+    Command(CommandError),
+    Pattern(&'static str),
+    MissingRepository(String),
+}
+
+impl fmt::Display for BorgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BorgError::Io(err) => write!(f, "io: {}", err),
+            BorgError::Command(err) => write!(f, "{}", err),
+            BorgError::Pattern(err) => write!(f, "{}", err),
+            BorgError::MissingRepository(repository) => write!(
+                f, "borg repository {} does not exist, set `auto_init = true` to create it", repository
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BorgError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_validate_compression() {
+        for spec in ["none", "lz4", "zstd", "zstd,10", "zlib,0", "lzma,9", "auto,lz4", "auto,zstd,3", "obfuscate,110,zstd,5"] {
+            assert!(validate_compression(spec).is_ok(), "{}", spec);
+        }
+        for spec in ["", "gzip", "zstd,0", "zstd,23", "lz4,1", "zlib,x", "auto", "obfuscate,zstd", "zstd,3,1"] {
+            assert!(validate_compression(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_borg_create_options_args() {
+        let options = BorgCreateOptions::default();
+        assert_eq!(options.to_args(), vec!["--one-file-system", "--numeric-ids", "--compression", "zstd"]);
+
+        let mut config = BorgConfig::default().assets.unwrap();
+        config.acl = Some(false);
+        config.xattrs = Some(false);
+        config.numeric_owner = Some(false);
+        config.one_file_system = Some(false);
+        config.compression = Some("auto,lz4".to_string());
+        config.chunker_params = Some("buzhash,19,23,21,4095".to_string());
+        assert_eq!(BorgCreateOptions::from_config(&config).to_args(), vec![
+            "--noacls", "--noxattrs", "--compression", "auto,lz4", "--chunker-params", "buzhash,19,23,21,4095",
+        ]);
+    }
+
+    fn config_with_target(target: &str) -> BorgConfig {
+        BorgConfig {
+            target: Some(BorgTargetConfig {
+                mode: Some("path".to_string()),
+                target: Some(target.to_string()),
+                auto_init: None,
+                encryption: None,
+                check_every: None,
+            }),
+            ..BorgConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_borg_archive_name() {
+        let mut config = config_with_target("/borg");
+        config.assets.as_mut().unwrap().archive = Some("{path}-{now:%Y}-{date}T{time}".to_string());
+        let source = PathBuf::from("/home/me/src/project");
+        let now = chrono::Local.with_ymd_and_hms(2024, 3, 9, 7, 5, 2).unwrap();
+        let context = TemplateContext::at(&source, Path::new("/home/me"), now);
+        let task = BorgCreateTask::with_context(source, &context, &config, vec![], vec![]).unwrap();
+        assert_eq!(task.repository, "/borg");
+        assert_eq!(task.archive, "src-project-2024-2024-03-09T070502");
+
+        let task = BorgCreateTask::new(
+            PathBuf::from("/home/me/src/project"),
+            Path::new("/home/me"),
+            &config_with_target("/borg::{name}"),
+            vec![],
+            vec![],
+        ).unwrap();
+        assert_eq!(task.location(), "/borg::project");
+    }
+
+    #[test]
+    fn test_borg_prune_commands() {
+        let mut config = config_with_target("/borg");
+        config.assets.as_mut().unwrap().archive = Some("{name}-{now:%Y%m%d}".to_string());
+        config.retention = Some(BorgRetentionConfig {
+            keep_within: None,
+            keep_daily: Some(7),
+            keep_weekly: Some(4),
+            keep_monthly: None,
+            keep_yearly: None,
+            glob_archives: None,
+            compact: None,
+        });
+        assert!(config.completion().is_ok());
+        let task = BorgCreateTask::new(
+            PathBuf::from("/home/me/project"),
+            Path::new("/home/me"),
+            &config,
+            vec![],
+            vec![],
+        ).unwrap();
+        let mut command_list = Some(Vec::new());
+        task.execute(&mut command_list).unwrap();
+        let commands = command_list.unwrap();
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0].starts_with("\"borg\" \"create\""));
+        assert_eq!(commands[1], "\"borg\" \"prune\" \"--list\" \"--glob-archives\" \"project-[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]\" \"--keep-daily\" \"7\" \"--keep-weekly\" \"4\" \"/borg\"");
+        assert_eq!(commands[2], "\"borg\" \"compact\" \"/borg\"");
+
+        config.retention.as_mut().unwrap().keep_daily = None;
+        config.retention.as_mut().unwrap().keep_weekly = None;
+        assert!(config.completion().is_err());
+    }
+
+    /// Whether the `glob_archives` of `expand_glob`, made of literals and `[...]` classes,
+    /// matches `name`.
+    fn glob_matches(glob: &str, name: &str) -> bool {
+        let mut name = name.chars();
+        let mut glob = glob.chars();
+        while let Some(g) = glob.next() {
+            let class: Vec<char> = if g == '[' {
+                let mut class = vec![glob.next().unwrap()];
+                class.extend(glob.by_ref().take_while(|&c| c != ']'));
+                class
+            } else {
+                vec![g]
+            };
+            let Some(c) = name.next() else { return false };
+            let in_class = class.windows(3).any(|w| w[1] == '-' && w[0] <= c && c <= w[2]) || class.contains(&c);
+            if !in_class {
+                return false;
+            }
+        }
+        name.next().is_none()
+    }
+
+    #[test]
+    fn test_prune_glob_of_siblings() {
+        let mut config = config_with_target("/borg");
+        config.retention = Some(BorgRetentionConfig {
+            keep_within: None,
+            keep_daily: Some(7),
+            keep_weekly: None,
+            keep_monthly: None,
+            keep_yearly: None,
+            glob_archives: None,
+            compact: None,
+        });
+        // both archive into `/borg` with the default template
+        let task = |source: &str| BorgCreateTask::new(
+            PathBuf::from(source),
+            Path::new("/home/me"),
+            &config,
+            vec![],
+            vec![],
+        ).unwrap();
+        let project = task("/home/me/src/project");
+        let sibling = task("/home/me/src/project-foo");
+        let project_glob = &project.prune.as_ref().unwrap().glob_archives;
+        let sibling_glob = &sibling.prune.as_ref().unwrap().glob_archives;
+        assert!(glob_matches(project_glob, &project.archive), "{} {}", project_glob, project.archive);
+        assert!(glob_matches(sibling_glob, &sibling.archive), "{} {}", sibling_glob, sibling.archive);
+        assert!(!glob_matches(project_glob, &sibling.archive), "{} {}", project_glob, sibling.archive);
+        assert!(!glob_matches(sibling_glob, &project.archive), "{} {}", sibling_glob, project.archive);
+
+        // no glob can be derived from a month name
+        config.assets.as_mut().unwrap().archive = Some("{path}-{now:%b}".to_string());
+        assert!(BorgCreateTask::new(PathBuf::from("/home/me/src/project"), Path::new("/home/me"), &config, vec![], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_borg_create_options_inheritance() {
+        let mut parent = BorgConfig::default().heritage.unwrap();
+        parent.compression = Some("lzma,6".to_string());
+        parent.numeric_owner = Some(false);
+        let mut child = BorgConfig::default().assets.unwrap();
+        child.numeric_owner = Some(true);
+        let merged = child.inherit_from(Some(&parent));
+        assert_eq!(merged.compression.as_deref(), Some("lzma,6"));
+        assert_eq!(merged.numeric_owner, Some(true));
+    }
+}
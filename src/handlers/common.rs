@@ -0,0 +1,40 @@
+use std::{fmt, path::Path, process::Output};
+
+/// An external program of a backend exited unsuccessfully.
+#[derive(Debug)]
+pub struct CommandError {
+    pub program: &'static str,
+    pub status: Option<i32>,
+    pub message: String,
+}
+
+impl CommandError {
+    /// The failure of `program` that produced `output`, described by its stderr.
+    pub fn from_output(program: &'static str, output: &Output) -> Self {
+        CommandError {
+            program,
+            status: output.status.code(),
+            message: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(code) => write!(f, "{} exited with code {}: {}", self.program, code, self.message.trim()),
+            None => write!(f, "{} was terminated by a signal: {}", self.program, self.message.trim()),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// `location` with a relative local path resolved against `base`. A location with a `host:` or
+/// `scheme:` prefix before its first `/`, e.g. `host:path` or `sftp:host:/path`, is kept as-is.
+pub fn resolve_local_location(location: &str, base: &Path) -> String {
+    match location.find(':') {
+        Some(colon) if !location[..colon].contains('/') => location.to_string(),
+        _ => base.join(location).to_string_lossy().to_string(),
+    }
+}
@@ -1,6 +1,6 @@
 use std::convert::TryFrom;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum BorgPattern {
@@ -16,6 +16,88 @@ pub struct GitIgnorePattern {
     pub pattern: String,
 }
 
+/// A single exclude rule in rsync's `--filter` syntax, e.g. `- /target`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RsyncFilterRule {
+    pub rule: String,
+}
+
+impl fmt::Display for RsyncFilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rule)
+    }
+}
+
+/// An exclude pattern for GNU tar. Anchored patterns match member names, which
+/// start with `./` since archives are created from inside the source directory.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TarExcludePattern {
+    pub pattern: String,
+    pub anchored: bool,
+}
+
+/// An exclude pattern for restic, which matches patterns against absolute paths.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResticExcludePattern {
+    pub pattern: String,
+    pub ignore_case: bool,
+}
+
+impl ResticExcludePattern {
+    /// Convert a borg pattern relative to `base`; the results are passed as `--iexclude` if `ignore_case`.
+    /// A fnmatch pattern can take several restic patterns, see `restic_fnmatch_patterns`.
+    pub fn from_borg_pattern(borg_pattern: BorgPattern, base: &Path, ignore_case: bool) -> Result<Vec<Self>, &'static str> {
+        let base = escape_wildcards(&base.to_string_lossy().replace('\\', "/"));
+        let anchored = |p: &str| format!("{}/{}", base.trim_end_matches('/'), p.trim_start_matches('/').trim_end_matches('/'));
+        let patterns = match borg_pattern {
+            BorgPattern::FnMatch(p) => restic_fnmatch_patterns(&p).iter().map(|p| anchored(p)).collect(),
+            BorgPattern::Shell(p) => {
+                match p.strip_prefix("**/") {
+                    // restic matches relative patterns at any depth
+                    Some(rest) if !rest.trim_end_matches('/').contains('/') => vec![rest.trim_end_matches('/').to_string()],
+                    _ => vec![anchored(&p)],
+                }
+            },
+            BorgPattern::Regex(_) => return Err("Cannot convert regex pattern to restic exclude pattern"),
+            BorgPattern::PathPrefix(p) => vec![anchored(&p)],
+            BorgPattern::PathFullMatch(p) => vec![anchored(&p)],
+        };
+        Ok(patterns.into_iter().map(|pattern| ResticExcludePattern { pattern, ignore_case }).collect())
+    }
+}
+
+/// `*` in fnmatch also matches `/`, while restic only crosses directories with a `**` component.
+/// A `*` at either end of a component becomes `**/*` or `*/**`; one inside a component can stay
+/// within it or span several, which takes two patterns.
+fn restic_fnmatch_patterns(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut patterns = vec![String::new()];
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '*' && i > 0 && chars[i - 1] == '*' {
+            continue;
+        }
+        if c != '*' {
+            patterns.iter_mut().for_each(|p| p.push(c));
+            continue;
+        }
+        let next = chars[i..].iter().find(|&&c| c != '*');
+        let at_start = i == 0 || chars[i - 1] == '/';
+        match next {
+            // restic excludes everything below a matched path anyway
+            None => patterns.iter_mut().for_each(|p| p.push('*')),
+            Some('/') => patterns.iter_mut().for_each(|p| p.push_str("*/**")),
+            Some(_) if at_start => patterns.iter_mut().for_each(|p| p.push_str("**/*")),
+            Some(_) => {
+                patterns = patterns
+                    .into_iter()
+                    .flat_map(|p| [format!("{}*", p), format!("{}*/**/*", p)])
+                    .collect();
+            },
+        }
+    }
+    patterns
+}
+
 impl fmt::Display for BorgPattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -111,6 +193,191 @@ impl TryFrom<BorgPattern> for GitIgnorePattern {
     }
 }
 
+impl TryFrom<BorgPattern> for RsyncFilterRule {
+    type Error = &'static str;
+
+    // Patterns are anchored to the transfer root, except `**/name` which rsync
+    // already matches at any depth when written without a slash.
+    fn try_from(borg_pattern: BorgPattern) -> Result<Self, Self::Error> {
+        let anchored = |p: &str| format!("- /{}", p.trim_start_matches('/').trim_end_matches('/'));
+        match borg_pattern {
+            BorgPattern::FnMatch(p) => {
+                // `*` in fnmatch also matches `/`, which is `**` in rsync
+                let mut converted = String::new();
+                let mut chars = p.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if c == '*' {
+                        while chars.peek() == Some(&'*') {
+                            chars.next();
+                        }
+                        converted.push_str("**");
+                    } else {
+                        converted.push(c);
+                    }
+                }
+                Ok(RsyncFilterRule { rule: anchored(&converted) })
+            },
+            BorgPattern::Shell(p) => {
+                match p.strip_prefix("**/") {
+                    Some(rest) if !rest.trim_end_matches('/').contains('/') => {
+                        Ok(RsyncFilterRule { rule: format!("- {}", rest) })
+                    },
+                    _ => Ok(RsyncFilterRule { rule: anchored(&p) }),
+                }
+            },
+            BorgPattern::Regex(_) => Err("Cannot convert regex pattern to rsync filter rule"),
+            BorgPattern::PathPrefix(p) => Ok(RsyncFilterRule { rule: anchored(&p) }),
+            BorgPattern::PathFullMatch(p) => Ok(RsyncFilterRule { rule: anchored(&p) }),
+        }
+    }
+}
+
+impl TryFrom<BorgPattern> for TarExcludePattern {
+    type Error = &'static str;
+
+    // tar wildcards match `/` in exclude patterns, so fnmatch and shell patterns need no translation.
+    fn try_from(borg_pattern: BorgPattern) -> Result<Self, Self::Error> {
+        let anchored = |p: &str| TarExcludePattern {
+            pattern: format!("./{}", p.trim_start_matches('/').trim_end_matches('/')),
+            anchored: true,
+        };
+        match borg_pattern {
+            BorgPattern::FnMatch(p) => Ok(anchored(&p)),
+            BorgPattern::Shell(p) => {
+                match p.strip_prefix("**/") {
+                    Some(rest) if !rest.trim_end_matches('/').contains('/') => {
+                        Ok(TarExcludePattern { pattern: rest.trim_end_matches('/').to_string(), anchored: false })
+                    },
+                    _ => Ok(anchored(&p)),
+                }
+            },
+            BorgPattern::Regex(_) => Err("Cannot convert regex pattern to tar exclude pattern"),
+            BorgPattern::PathPrefix(p) => Ok(anchored(&p)),
+            BorgPattern::PathFullMatch(p) => Ok(anchored(&p)),
+        }
+    }
+}
+
+/// `text` with the wildcards `*`, `?`, `[` and the escape `\\` itself escaped by a backslash.
+pub fn escape_wildcards(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// An exclude pathspec for `git add`, a glob relative to the workdir which excludes the paths
+/// it matches and everything below them. Shown as `:(exclude,glob)<pattern>`; libgit2 knows no
+/// pathspec magic, so its callbacks use `matches` instead.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GitExcludePathspec {
+    pub pattern: String,
+}
+
+impl fmt::Display for GitExcludePathspec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ":(exclude,glob){}", self.pattern)
+    }
+}
+
+impl GitExcludePathspec {
+    /// Exclude `path` literally, if it is inside `workdir`.
+    pub fn from_path(path: &Path, workdir: &Path) -> Option<Self> {
+        let relative = path.strip_prefix(workdir).ok()?.to_string_lossy().replace('\\', "/");
+        if relative.is_empty() {
+            return None;
+        }
+        Some(GitExcludePathspec { pattern: escape_wildcards(&relative) })
+    }
+
+    /// Whether the workdir-relative `path`, with `/` separators, or one of its parent
+    /// directories matches.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        path.match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .chain(std::iter::once(path))
+            .any(|prefix| wildmatch(self.pattern.as_bytes(), prefix.as_bytes()))
+    }
+}
+
+impl TryFrom<GitIgnorePattern> for GitExcludePathspec {
+    type Error = &'static str;
+
+    // like gitignore, a pattern without inner slash matches at any depth
+    fn try_from(git_pattern: GitIgnorePattern) -> Result<Self, Self::Error> {
+        let pattern = git_pattern.pattern.trim_end_matches('/');
+        if pattern.starts_with('!') {
+            return Err("Negation is not supported");
+        }
+        if pattern.is_empty() {
+            return Err("Empty pattern");
+        }
+        let pattern = if pattern.replace("\\/", "").contains('/') {
+            pattern.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+        Ok(GitExcludePathspec { pattern })
+    }
+}
+
+/// git's wildmatch with `WM_PATHNAME`: `*`, `?` and classes do not match `/`, `**/` matches
+/// any number of directories and a trailing `/**` everything below.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            wildmatch(rest, text)
+                || text.iter().enumerate().any(|(i, &c)| c == b'/' && wildmatch(rest, &text[i + 1..]))
+        },
+        [b'*', b'*'] => true,
+        [b'*', rest @ ..] => {
+            let rest = if rest.first() == Some(&b'*') { &rest[1..] } else { rest };
+            for i in 0..=text.len() {
+                if wildmatch(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    return false;
+                }
+            }
+            false
+        },
+        [b'?', rest @ ..] => !text.is_empty() && text[0] != b'/' && wildmatch(rest, &text[1..]),
+        [b'[', rest @ ..] => {
+            let Some((&c, text_rest)) = text.split_first() else { return false };
+            let (negated, class) = match rest.first() {
+                Some(b'!' | b'^') => (true, &rest[1..]),
+                _ => (false, rest),
+            };
+            // a `]` right after the opening bracket is literal
+            let Some(end) = class.iter().skip(1).position(|&b| b == b']').map(|i| i + 1) else {
+                return c == b'[' && wildmatch(rest, text_rest);
+            };
+            let items = &class[..end];
+            let mut matched = false;
+            let mut i = 0;
+            while i < items.len() {
+                if i + 2 < items.len() && items[i + 1] == b'-' {
+                    matched |= items[i] <= c && c <= items[i + 2];
+                    i += 3;
+                } else {
+                    matched |= items[i] == c;
+                    i += 1;
+                }
+            }
+            c != b'/' && matched != negated && wildmatch(&class[end + 1..], text_rest)
+        },
+        [b'\\', escaped, rest @ ..] => text.first() == Some(escaped) && wildmatch(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && wildmatch(rest, &text[1..]),
+    }
+}
+
 pub fn replace_possibly_escaped(str: String, replace_from: &str, replace_to: &str) -> String {
     let mut str_iter = str.chars().peekable();
     let mut from_iter = replace_from.chars();
@@ -228,6 +495,83 @@ mod tests {
         assert_eq!(git_pattern.pattern, "/dir/file");
     }
 
+    #[test]
+    fn test_borgpattern_to_rsync_filter_rule() {
+        let rule = RsyncFilterRule::try_from(BorgPattern::Shell("**/*.o".to_string())).unwrap();
+        assert_eq!(rule.rule, "- *.o");
+        let rule = RsyncFilterRule::try_from(BorgPattern::Shell("**/build/".to_string())).unwrap();
+        assert_eq!(rule.rule, "- build/");
+        let rule = RsyncFilterRule::try_from(BorgPattern::Shell("dir/file".to_string())).unwrap();
+        assert_eq!(rule.rule, "- /dir/file");
+        let rule = RsyncFilterRule::try_from(BorgPattern::PathPrefix("dir/".to_string())).unwrap();
+        assert_eq!(rule.rule, "- /dir");
+        let rule = RsyncFilterRule::try_from(BorgPattern::FnMatch("cache/*.tmp".to_string())).unwrap();
+        assert_eq!(rule.rule, "- /cache/**.tmp");
+        assert!(RsyncFilterRule::try_from(BorgPattern::Regex("^a".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_borgpattern_to_tar_exclude_pattern() {
+        let pattern = TarExcludePattern::try_from(BorgPattern::Shell("**/*.o".to_string())).unwrap();
+        assert_eq!(pattern, TarExcludePattern { pattern: "*.o".to_string(), anchored: false });
+        let pattern = TarExcludePattern::try_from(BorgPattern::Shell("dir/file".to_string())).unwrap();
+        assert_eq!(pattern, TarExcludePattern { pattern: "./dir/file".to_string(), anchored: true });
+        let pattern = TarExcludePattern::try_from(BorgPattern::PathPrefix("target/".to_string())).unwrap();
+        assert_eq!(pattern, TarExcludePattern { pattern: "./target".to_string(), anchored: true });
+        assert!(TarExcludePattern::try_from(BorgPattern::Regex("^a".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_borgpattern_to_restic_exclude_pattern() {
+        let base = Path::new("/data/project");
+        let convert = |pattern: BorgPattern| -> Vec<String> {
+            ResticExcludePattern::from_borg_pattern(pattern, base, false).unwrap().into_iter().map(|p| p.pattern).collect()
+        };
+        let patterns = ResticExcludePattern::from_borg_pattern(BorgPattern::Shell("**/*.o".to_string()), base, false).unwrap();
+        assert_eq!(patterns, vec![ResticExcludePattern { pattern: "*.o".to_string(), ignore_case: false }]);
+        let patterns = ResticExcludePattern::from_borg_pattern(BorgPattern::Shell("/dir/file".to_string()), base, true).unwrap();
+        assert_eq!(patterns, vec![ResticExcludePattern { pattern: "/data/project/dir/file".to_string(), ignore_case: true }]);
+        assert_eq!(convert(BorgPattern::PathPrefix("target/".to_string())), vec!["/data/project/target"]);
+        assert!(ResticExcludePattern::from_borg_pattern(BorgPattern::Regex("^a".to_string()), base, false).is_err());
+
+        // `*` in fnmatch also matches `/`
+        assert_eq!(convert(BorgPattern::FnMatch("src/*.o".to_string())), vec!["/data/project/src/**/*.o"]);
+        assert_eq!(convert(BorgPattern::FnMatch("build*/cache".to_string())), vec!["/data/project/build*/**/cache"]);
+        assert_eq!(convert(BorgPattern::FnMatch("logs/**".to_string())), vec!["/data/project/logs/*"]);
+        assert_eq!(convert(BorgPattern::FnMatch("a*b".to_string())), vec!["/data/project/a*b", "/data/project/a*/**/*b"]);
+
+        let patterns = ResticExcludePattern::from_borg_pattern(BorgPattern::PathPrefix("target".to_string()), Path::new("/data/p[1]"), false).unwrap();
+        assert_eq!(patterns[0].pattern, "/data/p\\[1]/target");
+    }
+
+    #[test]
+    fn test_gitignore_to_git_exclude_pathspec() {
+        let pathspec = GitExcludePathspec::try_from(GitIgnorePattern { pattern: "*.o".to_string() }).unwrap();
+        assert_eq!(pathspec.to_string(), ":(exclude,glob)**/*.o");
+        let pathspec = GitExcludePathspec::try_from(GitIgnorePattern { pattern: "/build/".to_string() }).unwrap();
+        assert_eq!(pathspec.pattern, "build");
+        assert!(GitExcludePathspec::try_from(GitIgnorePattern { pattern: "!keep".to_string() }).is_err());
+        let pathspec = GitExcludePathspec::from_path(Path::new("/repo/a[1]/b"), Path::new("/repo")).unwrap();
+        assert_eq!(pathspec.pattern, "a\\[1]/b");
+        assert!(GitExcludePathspec::from_path(Path::new("/other"), Path::new("/repo")).is_none());
+    }
+
+    #[test]
+    fn test_git_exclude_pathspec_matches() {
+        let pathspec = |pattern: &str| GitExcludePathspec { pattern: pattern.to_string() };
+        assert!(pathspec("**/*.o").matches("a.o"));
+        assert!(pathspec("**/*.o").matches("src/deep/a.o"));
+        assert!(!pathspec("**/*.o").matches("a.oo"));
+        assert!(pathspec("build").matches("build/out/x"));
+        assert!(!pathspec("build").matches("src/build"));
+        assert!(!pathspec("src/*.rs").matches("src/deep/a.rs"));
+        assert!(pathspec("src/**").matches("src/deep/a.rs"));
+        assert!(pathspec("file[0-9].txt").matches("file3.txt"));
+        assert!(!pathspec("file[!0-9].txt").matches("file3.txt"));
+        assert!(pathspec("a\\[1]/b").matches("a[1]/b/c"));
+        assert!(!pathspec("a?b").matches("a/b"));
+    }
+
     #[test]
     fn test_cargo_gitignore() {
         let cargo_gitignore = r#"
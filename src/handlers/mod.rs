@@ -1,7 +1,12 @@
 
 pub mod toml_config;
+pub mod common;
 pub mod git;
 pub mod borg;
+pub mod restic;
+pub mod rsync;
+pub mod tar;
+pub mod template;
 pub mod trigger;
 pub mod exclude;
 
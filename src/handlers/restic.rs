@@ -0,0 +1,448 @@
+use std::{fmt, path::{Path, PathBuf}, process::{Command, Output}};
+use bevy_reflect::Reflect;
+use serde::Deserialize;
+
+use crate::{log::{log_error, log_warn}, task::{PushTask, TaskError, TaskOutcome}};
+
+use super::{common::{resolve_local_location, CommandError}, exclude::{escape_wildcards, BorgPattern, ResticExcludePattern}, toml_config::{check_fields, inherit_on_recursion, CompletableConfig, HasInheritableConfig, InheritableConfig, OnRecursion}};
+
+// *************************************************************************** //
+// Configuration Types and Implementations
+// *************************************************************************** //
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct ResticTargetConfig {
+    pub mode: Option<String>,
+    /// Repository location, passed to restic as `--repo`; relative local paths are resolved
+    /// against the source.
+    pub target: Option<String>,
+    /// File holding the repository password, passed to restic as `--password-file`, relative to
+    /// the source. When absent, restic falls back to its own environment variables.
+    pub password_file: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct ResticConfig {
+    pub target: Option<ResticTargetConfig>,
+    pub assets: Option<ResticInheritableConfig>,
+    pub heritage: Option<ResticInheritableConfig>,
+}
+
+impl Default for ResticConfig {
+    fn default() -> Self {
+        ResticConfig {
+            target: None,
+            assets: Some(
+                ResticInheritableConfig {
+                    trigger_by: vec!["restic".to_string()].into(),
+                    exclude_list: None,
+                    extra_exclude_mode: vec!["git".to_string()].into(),
+                    exclude_ignore_case: None, // inherited, `false` if unset anywhere
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: None,
+                }
+            ),
+            heritage: Some(
+                ResticInheritableConfig {
+                    trigger_by: None, // temporarily this cannot be inherited.
+                    exclude_list: None,
+                    extra_exclude_mode: None,
+                    exclude_ignore_case: None,
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: Some(false),
+                }
+            ),
+        }
+    }
+}
+
+impl HasInheritableConfig for ResticConfig {
+    type M = ResticInheritableConfig;
+
+    fn get_heritage_config(&self) -> &ResticInheritableConfig {
+        self.heritage.as_ref().unwrap()
+    }
+    fn get_assets_config(&self) -> &ResticInheritableConfig {
+        self.assets.as_ref().unwrap()
+    }
+    fn get_assets_config_mut(&mut self) -> &mut Self::M {
+        self.assets.as_mut().unwrap()
+    }
+    fn get_heritage_config_mut(&mut self) -> &mut Self::M {
+        self.heritage.as_mut().unwrap()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct ResticInheritableConfig {
+    pub trigger_by: Option<Vec<String>>,
+    pub exclude_list: Option<Vec<String>>,
+    pub extra_exclude_mode: Option<Vec<String>>,
+    /// Pass the extra exclude patterns as `--iexclude` instead of `--exclude`.
+    pub exclude_ignore_case: Option<bool>,
+    pub on_recursion: Option<OnRecursion>,
+    pub ignore_child: Option<bool>,
+}
+
+impl InheritableConfig for ResticInheritableConfig {
+    fn inherit_from(&self, super_config: Option<&Self>) -> Self {
+        let mut this = self.clone();
+        this.on_recursion = inherit_on_recursion(&self.on_recursion, super_config.map(|c| &c.on_recursion));
+        if this.exclude_ignore_case.is_none() {
+            this.exclude_ignore_case = super_config.and_then(|c| c.exclude_ignore_case);
+        }
+        this
+    }
+}
+
+impl CompletableConfig for ResticConfig {
+    type CompletionResult = Result<Self, &'static str>;
+
+    fn is_complete(&self) -> bool {
+        if let Some(target) = &self.target {
+            if !check_fields!(target, mode, target) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_child) = &self.assets {
+            if !check_fields!(as_child, trigger_by, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_super) = &self.heritage {
+            if as_super.exclude_list.is_some() {
+                log_warn!("Exclude list as super has no effect yet.");
+            }
+            if !check_fields!(as_super, ignore_child, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn completion(&self) -> Self::CompletionResult {
+        let mut result = self.clone();
+        let default = ResticConfig::default();
+
+        // check target
+        if let Some(target) = &self.target {
+            if target.target.is_none() {
+                return Err("Target cannot be None");
+            }
+            if !["path"].contains(&target.mode.as_deref().unwrap_or("path")) {
+                return Err("Invalid target mode");
+            }
+            result.target.as_mut().unwrap().mode = Some("path".to_string());
+        } else {
+            return Err("Target config is required");
+        }
+
+        // complete as_child
+        if let Some(as_child) = &mut result.assets {
+            if as_child.trigger_by.is_none() {
+                as_child.trigger_by = default.assets.as_ref().unwrap().trigger_by.clone();
+            }
+            if as_child.on_recursion.is_none() {
+                as_child.on_recursion = default.assets.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.assets = default.assets;
+        }
+
+        // complete as_super
+        if let Some(as_super) = &mut result.heritage {
+            if as_super.ignore_child.is_none() {
+                as_super.ignore_child = default.heritage.as_ref().unwrap().ignore_child;
+            }
+            if as_super.on_recursion.is_none() {
+                as_super.on_recursion = default.heritage.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.heritage = default.heritage;
+        }
+
+        Ok(result)
+    }
+}
+
+// *************************************************************************** //
+// Task Types and Implementations
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub struct ResticBackupTask {
+    pub source: PathBuf,
+    pub repository: String,
+    pub password_file: Option<PathBuf>,
+    pub exclude_list: Vec<PathBuf>,
+    pub extra_exclude_patterns: Vec<BorgPattern>,
+    pub exclude_ignore_case: bool,
+}
+
+impl ResticBackupTask {
+    /// Build the task from a completed target config.
+    pub fn new(
+        source: PathBuf,
+        target: &ResticTargetConfig,
+        exclude_list: Vec<PathBuf>,
+        extra_exclude_patterns: Vec<BorgPattern>,
+        exclude_ignore_case: bool,
+    ) -> Result<Self, String> {
+        let repository = target.target.as_deref().ok_or("The restic repository is not set")?;
+        Ok(ResticBackupTask {
+            repository: resolve_local_location(repository, &source),
+            password_file: target.password_file.as_ref().map(|path| source.join(path)),
+            source,
+            exclude_list,
+            extra_exclude_patterns,
+            exclude_ignore_case,
+        })
+    }
+
+    /// `--exclude` and `--iexclude` options for `restic backup`.
+    fn exclude_options(&self) -> Result<Vec<String>, ResticError> {
+        let mut vec: Vec<String> = Vec::new();
+        self.restic_exclude_patterns()
+            .map_err(ResticError::Pattern)?
+            .into_iter()
+            .for_each(|p| {
+                vec.push(if p.ignore_case { "--iexclude" } else { "--exclude" }.to_string());
+                vec.push(p.pattern);
+            });
+        Ok(vec)
+    }
+
+    fn restic_exclude_patterns(&self) -> Result<Vec<ResticExcludePattern>, &'static str> {
+        let mut patterns = Vec::new();
+        for exclude_path in &self.exclude_list {
+            if exclude_path.strip_prefix(&self.source).is_err() {
+                return Err("Exclude path must be under source path");
+            }
+            // restic matches absolute paths, so the exclude list only needs its wildcards escaped
+            patterns.push(ResticExcludePattern {
+                pattern: escape_wildcards(&exclude_path.to_string_lossy().replace('\\', "/")),
+                ignore_case: false,
+            });
+        }
+        for pattern in &self.extra_exclude_patterns {
+            patterns.extend(ResticExcludePattern::from_borg_pattern(pattern.clone(), &self.source, self.exclude_ignore_case)?);
+        }
+        Ok(patterns)
+    }
+}
+
+/// The outcome of a finished `restic backup`.
+fn backup_outcome(output: &Output) -> Result<TaskOutcome, ResticError> {
+    // restic exits with 3 when some files could not be read, but the snapshot is still created.
+    match output.status.code() {
+        Some(0) => Ok(TaskOutcome::Succeeded),
+        Some(3) => {
+            log_warn!("restic backup is incomplete: {}", String::from_utf8_lossy(&output.stderr));
+            Ok(TaskOutcome::Succeeded)
+        },
+        _ => {
+            let stderr_cow = String::from_utf8_lossy(&output.stderr);
+            log_error!("Failed to create restic snapshot: {}", stderr_cow);
+            Err(ResticError::Command(CommandError::from_output("restic", output)))
+        },
+    }
+}
+
+impl PushTask for ResticBackupTask {
+    fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
+        let mut command = Command::new("restic");
+        command
+            .arg("--repo")
+            .arg(&self.repository);
+        if let Some(password_file) = &self.password_file {
+            command
+                .arg("--password-file")
+                .arg(password_file);
+        }
+        command
+            .arg("backup")
+            .arg("--one-file-system");
+
+        command.args(self.exclude_options()?);
+
+        command.arg(&self.source);
+
+        if let Some(command_list) = command_list {
+            command_list.push(format!("{:?}", command));
+            Ok(TaskOutcome::Succeeded)
+        } else {
+            let output = command.output().map_err(ResticError::Io)?;
+            Ok(backup_outcome(&output)?)
+        }
+    }
+
+    fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+        Ok(self.exclude_options()?)
+    }
+
+    fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        println!(
+            "Restic snapshot: [{}] -> [{}]",
+            self.source.canonicalize()?.display(),
+            self.repository
+        );
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "restic"
+    }
+
+    fn source(&self) -> &Path {
+        &self.source
+    }
+
+    fn target(&self) -> Option<String> {
+        Some(self.repository.to_string())
+    }
+
+    fn exclusive_resource(&self) -> Option<String> {
+        Some(format!("restic:{}", self.repository))
+    }
+}
+
+// *************************************************************************** //
+// Error Types
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub enum ResticError {
+    Io(std::io::Error),
+    Command(CommandError),
+    Pattern(&'static str),
+}
+
+impl fmt::Display for ResticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResticError::Io(err) => write!(f, "io: {}", err),
+            ResticError::Command(err) => write!(f, "{}", err),
+            ResticError::Pattern(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ResticError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_target(target: Option<&str>) -> ResticConfig {
+        ResticConfig {
+            target: Some(ResticTargetConfig {
+                mode: Some("path".to_string()),
+                target: target.map(str::to_string),
+                password_file: None,
+            }),
+            ..ResticConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_restic_config_requires_target() {
+        let config = config_with_target(None);
+        assert!(!config.is_complete());
+        assert!(config.completion().is_err());
+        assert!(ResticBackupTask::new(PathBuf::from("/data"), config.target.as_ref().unwrap(), vec![], vec![], false).is_err());
+        assert!(config_with_target(Some("/backup/restic")).is_complete());
+    }
+
+    #[test]
+    fn test_restic_config_completion() {
+        let config = ResticConfig { assets: None, heritage: None, ..config_with_target(Some("/backup/restic")) };
+        assert!(!config.is_complete());
+        let completed = config.completion().unwrap();
+        assert!(completed.is_complete());
+        assert_eq!(completed.assets.unwrap().trigger_by, Some(vec!["restic".to_string()]));
+        assert_eq!(completed.heritage.unwrap().ignore_child, Some(false));
+
+        let mut config = config_with_target(Some("/backup/restic"));
+        config.target.as_mut().unwrap().mode = Some("mirror".to_string());
+        assert!(config.completion().is_err());
+        let config = ResticConfig { target: None, ..ResticConfig::default() };
+        assert!(!config.is_complete());
+        assert!(config.completion().is_err());
+    }
+
+    #[test]
+    fn test_restic_backup_outcome() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let output = |code: i32| Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: b"unreadable file".to_vec(),
+        };
+        assert!(matches!(backup_outcome(&output(0)), Ok(TaskOutcome::Succeeded)));
+        // a snapshot was created, only some files could not be read
+        assert!(matches!(backup_outcome(&output(3)), Ok(TaskOutcome::Succeeded)));
+        match backup_outcome(&output(1)) {
+            Err(ResticError::Command(err)) => assert_eq!(err.status, Some(1)),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restic_relative_paths_resolved_against_source() {
+        let source = PathBuf::from("/data/project");
+        let mut target = ResticTargetConfig {
+            mode: Some("path".to_string()),
+            target: Some("../restic".to_string()),
+            password_file: Some("secrets/password".to_string()),
+        };
+        let task = ResticBackupTask::new(source.clone(), &target, vec![], vec![], false).unwrap();
+        assert_eq!(task.repository, "/data/project/../restic");
+        assert_eq!(task.password_file, Some(PathBuf::from("/data/project/secrets/password")));
+
+        target.target = Some("sftp:backup@host:/srv/restic".to_string());
+        target.password_file = Some("/etc/restic/password".to_string());
+        let task = ResticBackupTask::new(source, &target, vec![], vec![], false).unwrap();
+        assert_eq!(task.repository, "sftp:backup@host:/srv/restic");
+        assert_eq!(task.password_file, Some(PathBuf::from("/etc/restic/password")));
+    }
+
+    #[test]
+    fn test_restic_dry_run_command() {
+        let task = ResticBackupTask {
+            source: PathBuf::from("/data/project"),
+            repository: "/backup/restic".to_string(),
+            password_file: Some(PathBuf::from("/etc/restic/password")),
+            exclude_list: vec![PathBuf::from("/data/project/child"), PathBuf::from("/data/project/notes[1]")],
+            extra_exclude_patterns: vec![BorgPattern::Shell("**/*.o".to_string())],
+            exclude_ignore_case: true,
+        };
+        let mut command_list = Some(Vec::new());
+        task.execute(&mut command_list).unwrap();
+        assert_eq!(command_list.unwrap(), vec![
+            "\"restic\" \"--repo\" \"/backup/restic\" \"--password-file\" \"/etc/restic/password\" \
+            \"backup\" \"--one-file-system\" \"--exclude\" \"/data/project/child\" \"--exclude\" \"/data/project/notes\\\\[1]\" \"--iexclude\" \"*.o\" \
+            \"/data/project\"".to_string()
+        ]);
+    }
+
+    #[test]
+    fn test_restic_rejects_foreign_exclude_path() {
+        let task = ResticBackupTask {
+            source: PathBuf::from("/data/project"),
+            repository: "/backup/restic".to_string(),
+            password_file: None,
+            exclude_list: vec![PathBuf::from("/elsewhere")],
+            extra_exclude_patterns: vec![],
+            exclude_ignore_case: false,
+        };
+        assert!(task.execute(&mut Some(Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+use std::{fmt, path::{Path, PathBuf}, process::Command};
+use bevy_reflect::Reflect;
+use serde::Deserialize;
+
+use crate::{log::{log_error, log_info, log_warn}, task::{PushTask, TaskError, TaskOutcome}};
+
+use super::{common::{resolve_local_location, CommandError}, exclude::{escape_wildcards, BorgPattern, RsyncFilterRule}, toml_config::{check_fields, inherit_on_recursion, CompletableConfig, HasInheritableConfig, InheritableConfig, OnRecursion}};
+
+// *************************************************************************** //
+// Configuration Types and Implementations
+// *************************************************************************** //
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct RsyncTargetConfig {
+    pub mode: Option<String>,
+    /// Destination directory, or `host:path` for a remote one; relative local paths are resolved
+    /// against the source.
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct RsyncConfig {
+    pub target: Option<RsyncTargetConfig>,
+    pub assets: Option<RsyncInheritableConfig>,
+    pub heritage: Option<RsyncInheritableConfig>,
+}
+
+impl Default for RsyncConfig {
+    fn default() -> Self {
+        RsyncConfig {
+            target: None,
+            assets: Some(
+                RsyncInheritableConfig {
+                    trigger_by: vec!["rsync".to_string()].into(),
+                    exclude_list: None,
+                    extra_exclude_mode: vec!["git".to_string()].into(),
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: None,
+                }
+            ),
+            heritage: Some(
+                RsyncInheritableConfig {
+                    trigger_by: None, // temporarily this cannot be inherited.
+                    exclude_list: None,
+                    extra_exclude_mode: None,
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: Some(false),
+                }
+            ),
+        }
+    }
+}
+
+impl HasInheritableConfig for RsyncConfig {
+    type M = RsyncInheritableConfig;
+
+    fn get_heritage_config(&self) -> &RsyncInheritableConfig {
+        self.heritage.as_ref().unwrap()
+    }
+    fn get_assets_config(&self) -> &RsyncInheritableConfig {
+        self.assets.as_ref().unwrap()
+    }
+    fn get_assets_config_mut(&mut self) -> &mut Self::M {
+        self.assets.as_mut().unwrap()
+    }
+    fn get_heritage_config_mut(&mut self) -> &mut Self::M {
+        self.heritage.as_mut().unwrap()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct RsyncInheritableConfig {
+    pub trigger_by: Option<Vec<String>>,
+    pub exclude_list: Option<Vec<String>>,
+    pub extra_exclude_mode: Option<Vec<String>>,
+    pub on_recursion: Option<OnRecursion>,
+    pub ignore_child: Option<bool>,
+}
+
+impl InheritableConfig for RsyncInheritableConfig {
+    fn inherit_from(&self, super_config: Option<&Self>) -> Self {
+        let mut this = self.clone();
+        this.on_recursion = inherit_on_recursion(&self.on_recursion, super_config.map(|c| &c.on_recursion));
+        this
+    }
+}
+
+impl CompletableConfig for RsyncConfig {
+    type CompletionResult = Result<Self, &'static str>;
+
+    fn is_complete(&self) -> bool {
+        if let Some(target) = &self.target {
+            if !check_fields!(target, mode, target) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_child) = &self.assets {
+            if !check_fields!(as_child, trigger_by, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_super) = &self.heritage {
+            if as_super.exclude_list.is_some() {
+                log_warn!("Exclude list as super has no effect yet.");
+            }
+            if !check_fields!(as_super, ignore_child, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn completion(&self) -> Self::CompletionResult {
+        let mut result = self.clone();
+        let default = RsyncConfig::default();
+
+        // check target
+        if let Some(target) = &self.target {
+            if target.target.is_none() {
+                return Err("Target cannot be None");
+            }
+            if !["path"].contains(&target.mode.as_deref().unwrap_or("path")) {
+                return Err("Invalid target mode");
+            }
+            result.target.as_mut().unwrap().mode = Some("path".to_string());
+        } else {
+            return Err("Target config is required");
+        }
+
+        // complete as_child
+        if let Some(as_child) = &mut result.assets {
+            if as_child.trigger_by.is_none() {
+                as_child.trigger_by = default.assets.as_ref().unwrap().trigger_by.clone();
+            }
+            if as_child.on_recursion.is_none() {
+                as_child.on_recursion = default.assets.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.assets = default.assets;
+        }
+
+        // complete as_super
+        if let Some(as_super) = &mut result.heritage {
+            if as_super.ignore_child.is_none() {
+                as_super.ignore_child = default.heritage.as_ref().unwrap().ignore_child;
+            }
+            if as_super.on_recursion.is_none() {
+                as_super.on_recursion = default.heritage.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.heritage = default.heritage;
+        }
+
+        Ok(result)
+    }
+}
+
+// *************************************************************************** //
+// Task Types and Implementations
+// *************************************************************************** //
+
+/// Mirrors the content of `source` into `target`, which can be a local directory
+/// or anything else rsync accepts as a destination (e.g. `host:path`).
+#[derive(Debug)]
+pub struct RsyncTask {
+    pub source: PathBuf,
+    pub target: String,
+    pub exclude_list: Vec<PathBuf>,
+    pub extra_exclude_patterns: Vec<BorgPattern>,
+}
+
+impl RsyncTask {
+    /// Build the task from a completed target config.
+    pub fn new(
+        source: PathBuf,
+        target: &RsyncTargetConfig,
+        exclude_list: Vec<PathBuf>,
+        extra_exclude_patterns: Vec<BorgPattern>,
+    ) -> Result<Self, String> {
+        let target = target.target.as_deref().ok_or("The rsync target is not set")?;
+        Ok(RsyncTask {
+            target: resolve_local_location(target, &source),
+            source,
+            exclude_list,
+            extra_exclude_patterns,
+        })
+    }
+
+    /// `--exclude` and `--filter` options for `rsync`.
+    fn exclude_options(&self) -> Result<Vec<String>, RsyncError> {
+        let mut vec: Vec<String> = Vec::new();
+        // anchored to the transfer root, i.e. `source`
+        for exclude_path in &self.exclude_list {
+            let relative = exclude_path
+                .strip_prefix(&self.source)
+                .map_err(|_| RsyncError::Pattern("Exclude path must be under source path"))?
+                .to_string_lossy()
+                .replace('\\', "/");
+            // rsync only treats a backslash as an escape in patterns with wildcards
+            let relative = if relative.contains(['*', '?', '[']) { escape_wildcards(&relative) } else { relative };
+            vec.push("--exclude".to_string());
+            vec.push(format!("/{}", relative));
+        }
+        self.rsync_filter_rules()
+            .map_err(RsyncError::Pattern)?
+            .into_iter()
+            .for_each(|rule| {
+                vec.push("--filter".to_string());
+                vec.push(rule.to_string());
+            });
+        Ok(vec)
+    }
+
+    fn rsync_filter_rules(&self) -> Result<Vec<RsyncFilterRule>, &'static str> {
+        let mut rules = Vec::new();
+        for pattern in &self.extra_exclude_patterns {
+            rules.push(RsyncFilterRule::try_from(pattern.clone())?);
+        }
+        Ok(rules)
+    }
+
+    fn command(&self) -> Result<Command, RsyncError> {
+        let mut command = Command::new("rsync");
+        command
+            .arg("--archive")
+            .arg("--delete")
+            .arg("--one-file-system");
+
+        command.args(self.exclude_options()?);
+
+        // the trailing slash makes rsync copy the content of `source` rather than the directory itself
+        let mut source = self.source.clone().into_os_string();
+        source.push("/");
+        command
+            .arg(source)
+            .arg(&self.target);
+        Ok(command)
+    }
+}
+
+impl PushTask for RsyncTask {
+    fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
+        let mut command = self.command()?;
+
+        if let Some(command_list) = command_list {
+            command_list.push(format!("{:?}", command));
+            Ok(TaskOutcome::Succeeded)
+        } else {
+            let output = command.output().map_err(RsyncError::Io)?;
+            if output.status.success() {
+                log_info!("Successfully synchronized {} to {}", self.source.display(), self.target);
+                Ok(TaskOutcome::Succeeded)
+            } else {
+                let stderr_cow = String::from_utf8_lossy(&output.stderr);
+                log_error!("Failed to synchronize with rsync: {}", stderr_cow);
+                Err(RsyncError::Command(CommandError::from_output("rsync", &output)).into())
+            }
+        }
+    }
+
+    fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+        Ok(self.exclude_options()?)
+    }
+
+    fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        println!(
+            "Rsync: [{}] -> [{}]",
+            self.source.canonicalize()?.display(),
+            self.target
+        );
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "rsync"
+    }
+
+    fn source(&self) -> &Path {
+        &self.source
+    }
+
+    fn target(&self) -> Option<String> {
+        Some(self.target.to_string())
+    }
+
+    fn exclusive_resource(&self) -> Option<String> {
+        Some(format!("rsync:{}", self.target))
+    }
+}
+
+// *************************************************************************** //
+// Error Types
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub enum RsyncError {
+    Io(std::io::Error),
+    Command(CommandError),
+    Pattern(&'static str),
+}
+
+impl fmt::Display for RsyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RsyncError::Io(err) => write!(f, "io: {}", err),
+            RsyncError::Command(err) => write!(f, "{}", err),
+            RsyncError::Pattern(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RsyncError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_task() -> RsyncTask {
+        RsyncTask {
+            source: PathBuf::from("/data/project"),
+            target: "/backup/project".to_string(),
+            exclude_list: vec![PathBuf::from("/data/project/vendor/lib")],
+            extra_exclude_patterns: vec![
+                BorgPattern::Shell("**/*.o".to_string()),
+                BorgPattern::PathPrefix("target/".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_rsync_config_requires_target() {
+        let mut config = RsyncConfig {
+            target: Some(RsyncTargetConfig { mode: Some("path".to_string()), target: None }),
+            ..RsyncConfig::default()
+        };
+        assert!(!config.is_complete());
+        assert!(config.completion().is_err());
+        assert!(RsyncTask::new(PathBuf::from("/data"), config.target.as_ref().unwrap(), vec![], vec![]).is_err());
+
+        config.target.as_mut().unwrap().target = Some("/backup".to_string());
+        config.target.as_mut().unwrap().mode = None;
+        assert!(!config.is_complete());
+        let completed = config.completion().unwrap();
+        assert!(completed.is_complete());
+        assert_eq!(completed.target.unwrap().mode.as_deref(), Some("path"));
+    }
+
+    #[test]
+    fn test_rsync_relative_target_resolved_against_source() {
+        let target = |target: &str| {
+            let config = RsyncTargetConfig { mode: Some("path".to_string()), target: Some(target.to_string()) };
+            RsyncTask::new(PathBuf::from("/data/project"), &config, vec![], vec![]).unwrap().target
+        };
+        assert_eq!(target("../mirror"), "/data/project/../mirror");
+        assert_eq!(target("/backup/mirror"), "/backup/mirror");
+        assert_eq!(target("host:mirror"), "host:mirror");
+        assert_eq!(target("rsync://host/module"), "rsync://host/module");
+    }
+
+    #[test]
+    fn test_rsync_exclude_pattern_options() {
+        let options = sample_task().exclude_pattern_options().unwrap();
+        assert_eq!(options, vec![
+            "--exclude", "/vendor/lib",
+            "--filter", "- *.o",
+            "--filter", "- /target",
+        ]);
+    }
+
+    #[test]
+    fn test_rsync_escapes_exclude_paths() {
+        let mut task = sample_task();
+        task.extra_exclude_patterns.clear();
+        task.exclude_list = vec![
+            PathBuf::from("/data/project/notes[1]*.md"),
+            PathBuf::from("/data/project/plain.md"),
+        ];
+        assert_eq!(task.exclude_pattern_options().unwrap(), vec![
+            "--exclude", "/notes\\[1]\\*.md",
+            "--exclude", "/plain.md",
+        ]);
+
+        task.exclude_list.push(PathBuf::from("/data/other"));
+        assert!(task.exclude_pattern_options().is_err());
+    }
+
+    #[test]
+    fn test_rsync_dry_run_command() {
+        let mut command_list = Some(Vec::new());
+        sample_task().execute(&mut command_list).unwrap();
+        let commands = command_list.unwrap();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].starts_with("\"rsync\" \"--archive\" \"--delete\""));
+        assert!(commands[0].ends_with("\"/data/project/\" \"/backup/project\""));
+    }
+
+    #[test]
+    fn test_rsync_rejects_regex() {
+        let mut task = sample_task();
+        task.extra_exclude_patterns.push(BorgPattern::Regex(".*".to_string()));
+        assert!(task.execute(&mut Some(Vec::new())).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires rsync, run with `cargo test -- --ignored`"]
+    fn test_rsync_to_local_directory() {
+        let root = std::env::temp_dir().join(format!("dionysius_rsync_{}", std::process::id()));
+        let source = root.join("source");
+        let target = root.join("target");
+        std::fs::create_dir_all(source.join("skipped")).unwrap();
+        std::fs::write(source.join("kept.txt"), "kept").unwrap();
+        std::fs::write(source.join("skipped/file.txt"), "skipped").unwrap();
+        std::fs::write(source.join("object.o"), "object").unwrap();
+
+        let task = RsyncTask {
+            source: source.clone(),
+            target: target.to_string_lossy().to_string(),
+            exclude_list: vec![source.join("skipped")],
+            extra_exclude_patterns: vec![BorgPattern::Shell("**/*.o".to_string())],
+        };
+        task.execute(&mut None).unwrap();
+
+        assert!(target.join("kept.txt").is_file());
+        assert!(!target.join("skipped").exists());
+        assert!(!target.join("object.o").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,464 @@
+use std::{fmt, path::{Path, PathBuf}, process::Command};
+use bevy_reflect::Reflect;
+use serde::Deserialize;
+
+use crate::{log::{log_error, log_info, log_warn}, task::{PushTask, TaskError, TaskOutcome}};
+
+use super::{common::CommandError, exclude::{escape_wildcards, BorgPattern, TarExcludePattern}, template::TemplateContext, toml_config::{check_fields, inherit_on_recursion, CompletableConfig, HasInheritableConfig, InheritableConfig, OnRecursion}};
+
+// *************************************************************************** //
+// Configuration Types and Implementations
+// *************************************************************************** //
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct TarTargetConfig {
+    pub mode: Option<String>,
+    /// Directory receiving the archives; relative paths are resolved against the source.
+    pub target: Option<String>,
+    /// Archive name template, without extension. See `TemplateContext` for placeholders.
+    pub name: Option<String>,
+    pub compression: Option<TarCompression>,
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct TarConfig {
+    pub target: Option<TarTargetConfig>,
+    pub assets: Option<TarInheritableConfig>,
+    pub heritage: Option<TarInheritableConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Reflect, PartialEq)]
+pub enum TarCompression {
+    #[default]
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+    #[serde(rename = "xz")]
+    Xz,
+    #[serde(rename = "none")]
+    None,
+}
+
+impl TarCompression {
+    fn tar_option(&self) -> Option<&'static str> {
+        match self {
+            TarCompression::Gzip => Some("--gzip"),
+            TarCompression::Zstd => Some("--zstd"),
+            TarCompression::Xz => Some("--xz"),
+            TarCompression::None => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            TarCompression::Gzip => "tar.gz",
+            TarCompression::Zstd => "tar.zst",
+            TarCompression::Xz => "tar.xz",
+            TarCompression::None => "tar",
+        }
+    }
+}
+
+impl Default for TarConfig {
+    fn default() -> Self {
+        TarConfig {
+            target: Some(TarTargetConfig {
+                mode: Some("path".to_string()),
+                target: None,
+                name: Some("{hostname}-{name}-{now:%Y%m%dT%H%M%S}".to_string()),
+                compression: Some(TarCompression::default()),
+            }),
+            assets: Some(
+                TarInheritableConfig {
+                    trigger_by: vec!["tar".to_string()].into(),
+                    exclude_list: None,
+                    extra_exclude_mode: vec!["git".to_string()].into(),
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: None,
+                }
+            ),
+            heritage: Some(
+                TarInheritableConfig {
+                    trigger_by: None, // temporarily this cannot be inherited.
+                    exclude_list: None,
+                    extra_exclude_mode: None,
+                    on_recursion: Some(OnRecursion::Inherit),
+                    ignore_child: Some(false),
+                }
+            ),
+        }
+    }
+}
+
+impl HasInheritableConfig for TarConfig {
+    type M = TarInheritableConfig;
+
+    fn get_heritage_config(&self) -> &TarInheritableConfig {
+        self.heritage.as_ref().unwrap()
+    }
+    fn get_assets_config(&self) -> &TarInheritableConfig {
+        self.assets.as_ref().unwrap()
+    }
+    fn get_assets_config_mut(&mut self) -> &mut Self::M {
+        self.assets.as_mut().unwrap()
+    }
+    fn get_heritage_config_mut(&mut self) -> &mut Self::M {
+        self.heritage.as_mut().unwrap()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Reflect)]
+pub struct TarInheritableConfig {
+    pub trigger_by: Option<Vec<String>>,
+    pub exclude_list: Option<Vec<String>>,
+    pub extra_exclude_mode: Option<Vec<String>>,
+    pub on_recursion: Option<OnRecursion>,
+    pub ignore_child: Option<bool>,
+}
+
+impl InheritableConfig for TarInheritableConfig {
+    fn inherit_from(&self, super_config: Option<&Self>) -> Self {
+        let mut this = self.clone();
+        this.on_recursion = inherit_on_recursion(&self.on_recursion, super_config.map(|c| &c.on_recursion));
+        this
+    }
+}
+
+impl CompletableConfig for TarConfig {
+    type CompletionResult = Result<Self, &'static str>;
+
+    fn is_complete(&self) -> bool {
+        if let Some(target) = &self.target {
+            if !check_fields!(target, mode, target, name, compression) {
+                return false;
+            }
+            // leave invalid templates to `completion`, which reports them
+            if TemplateContext::check(target.name.as_ref().unwrap()).is_err() {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_child) = &self.assets {
+            if !check_fields!(as_child, trigger_by, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        if let Some(as_super) = &self.heritage {
+            if as_super.exclude_list.is_some() {
+                log_warn!("Exclude list as super has no effect yet.");
+            }
+            if !check_fields!(as_super, ignore_child, on_recursion) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn completion(&self) -> Self::CompletionResult {
+        let mut result = self.clone();
+        let default = TarConfig::default();
+
+        // check and complete target
+        if let Some(target) = &mut result.target {
+            if target.target.is_none() {
+                return Err("Target cannot be None");
+            }
+            if !["path"].contains(&target.mode.as_deref().unwrap_or("path")) {
+                return Err("Invalid target mode");
+            }
+            let default_target = default.target.as_ref().unwrap();
+            if target.mode.is_none() {
+                target.mode = default_target.mode.clone();
+            }
+            if target.name.is_none() {
+                target.name = default_target.name.clone();
+            }
+            if target.compression.is_none() {
+                target.compression = default_target.compression.clone();
+            }
+            if TemplateContext::check(target.name.as_ref().unwrap()).is_err() {
+                return Err("Invalid archive name template");
+            }
+        } else {
+            return Err("Target config is required");
+        }
+
+        // complete as_child
+        if let Some(as_child) = &mut result.assets {
+            if as_child.trigger_by.is_none() {
+                as_child.trigger_by = default.assets.as_ref().unwrap().trigger_by.clone();
+            }
+            if as_child.on_recursion.is_none() {
+                as_child.on_recursion = default.assets.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.assets = default.assets;
+        }
+
+        // complete as_super
+        if let Some(as_super) = &mut result.heritage {
+            if as_super.ignore_child.is_none() {
+                as_super.ignore_child = default.heritage.as_ref().unwrap().ignore_child;
+            }
+            if as_super.on_recursion.is_none() {
+                as_super.on_recursion = default.heritage.as_ref().unwrap().on_recursion.clone();
+            }
+        } else {
+            result.heritage = default.heritage;
+        }
+
+        Ok(result)
+    }
+}
+
+// *************************************************************************** //
+// Task Types and Implementations
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub struct TarArchiveTask {
+    pub source: PathBuf,
+    /// Full path of the archive to be written.
+    pub output: PathBuf,
+    pub compression: TarCompression,
+    pub exclude_list: Vec<PathBuf>,
+    pub extra_exclude_patterns: Vec<BorgPattern>,
+}
+
+impl TarArchiveTask {
+    /// Build the task from a completed `[tar.target]`, expanding the name template now
+    /// so that preview and execution agree on the archive path.
+    pub fn new(
+        source: PathBuf,
+        root: &Path,
+        target: &TarTargetConfig,
+        exclude_list: Vec<PathBuf>,
+        extra_exclude_patterns: Vec<BorgPattern>,
+    ) -> Result<Self, String> {
+        let compression = target.compression.clone().unwrap_or_default();
+        let name = TemplateContext::for_source(&source, root).expand(target.name.as_ref().unwrap())?;
+        let output_dir = source.join(target.target.as_ref().unwrap());
+        // the archives of earlier runs would be archived again, see `tar_exclude_patterns`
+        if output_dir.components().eq(source.components()) {
+            return Err(format!("The tar output directory must not be the source {} itself", source.display()));
+        }
+        let output = output_dir.join(format!("{}.{}", name, compression.extension()));
+        Ok(TarArchiveTask {
+            source,
+            output,
+            compression,
+            exclude_list,
+            extra_exclude_patterns,
+        })
+    }
+
+    /// `--exclude` options for `tar --create`, grouped by anchoring.
+    fn exclude_options(&self) -> Result<Vec<String>, TarError> {
+        let patterns = self.tar_exclude_patterns().map_err(TarError::Pattern)?;
+        let mut vec: Vec<String> = Vec::new();
+        // `--anchored` and `--no-anchored` apply to the `--exclude` options following them
+        for anchored in [true, false] {
+            let group: Vec<&TarExcludePattern> = patterns.iter().filter(|p| p.anchored == anchored).collect();
+            if group.is_empty() {
+                continue;
+            }
+            vec.push(if anchored { "--anchored" } else { "--no-anchored" }.to_string());
+            group.into_iter().for_each(|p| {
+                vec.push("--exclude".to_string());
+                vec.push(p.pattern.clone());
+            });
+        }
+        Ok(vec)
+    }
+
+    fn tar_exclude_patterns(&self) -> Result<Vec<TarExcludePattern>, &'static str> {
+        let mut patterns = Vec::new();
+        // paths are literal, while tar reads every `--exclude` as a wildcard pattern
+        for exclude_path in &self.exclude_list {
+            if let Ok(relative) = exclude_path.strip_prefix(&self.source) {
+                patterns.push(TarExcludePattern {
+                    pattern: format!("./{}", escape_wildcards(&relative.to_string_lossy().replace('\\', "/"))),
+                    anchored: true,
+                });
+            } else {
+                return Err("Exclude path must be under source path");
+            }
+        }
+        // never archive the archives, this one and those of earlier runs, when they are
+        // written inside the source
+        if let Some(Ok(relative)) = self.output.parent().map(|dir| dir.strip_prefix(&self.source)) {
+            patterns.push(TarExcludePattern {
+                pattern: format!("./{}", escape_wildcards(&relative.to_string_lossy().replace('\\', "/"))),
+                anchored: true,
+            });
+        }
+        for pattern in &self.extra_exclude_patterns {
+            patterns.push(TarExcludePattern::try_from(pattern.clone())?);
+        }
+        Ok(patterns)
+    }
+}
+
+impl PushTask for TarArchiveTask {
+    fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
+        let mut command = Command::new("tar");
+        command
+            .arg("--create")
+            .arg("--file")
+            .arg(&self.output);
+        if let Some(option) = self.compression.tar_option() {
+            command.arg(option);
+        }
+        command
+            .arg("--directory")
+            .arg(&self.source);
+
+        command.args(self.exclude_options()?);
+        command.arg(".");
+
+        if let Some(command_list) = command_list {
+            command_list.push(format!("{:?}", command));
+            Ok(TaskOutcome::Succeeded)
+        } else {
+            if let Some(output_dir) = self.output.parent() {
+                std::fs::create_dir_all(output_dir).map_err(TarError::Io)?;
+            }
+            let output = command.output().map_err(TarError::Io)?;
+            if output.status.success() {
+                log_info!("Successfully archived to {}", self.output.display());
+                Ok(TaskOutcome::Succeeded)
+            } else {
+                let stderr_cow = String::from_utf8_lossy(&output.stderr);
+                log_error!("Failed to create tar archive: {}", stderr_cow);
+                Err(TarError::Command(CommandError::from_output("tar", &output)).into())
+            }
+        }
+    }
+
+    fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+        Ok(self.exclude_options()?)
+    }
+
+    fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        println!(
+            "Tar archive: [{}] -> [{}]",
+            self.source.canonicalize()?.display(),
+            self.output.display()
+        );
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "tar"
+    }
+
+    fn source(&self) -> &Path {
+        &self.source
+    }
+
+    fn target(&self) -> Option<String> {
+        Some(self.output.display().to_string())
+    }
+
+    fn exclusive_resource(&self) -> Option<String> {
+        Some(format!("tar:{}", self.output.display()))
+    }
+}
+
+// *************************************************************************** //
+// Error Types
+// *************************************************************************** //
+
+#[derive(Debug)]
+pub enum TarError {
+    Io(std::io::Error),
+    Command(CommandError),
+    Pattern(&'static str),
+}
+
+impl fmt::Display for TarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TarError::Io(err) => write!(f, "io: {}", err),
+            TarError::Command(err) => write!(f, "{}", err),
+            TarError::Pattern(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for TarError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_exclude_pattern_options() {
+        let task = TarArchiveTask {
+            source: PathBuf::from("/data/project"),
+            output: PathBuf::from("/data/project/archives/project.tar.gz"),
+            compression: TarCompression::Gzip,
+            exclude_list: vec![PathBuf::from("/data/project/child"), PathBuf::from("/data/project/notes[1]*")],
+            extra_exclude_patterns: vec![BorgPattern::Shell("**/*.o".to_string())],
+        };
+        assert_eq!(task.exclude_pattern_options().unwrap(), vec![
+            "--anchored",
+            "--exclude", "./child",
+            "--exclude", "./notes\\[1]\\*",
+            "--exclude", "./archives",
+            "--no-anchored",
+            "--exclude", "*.o",
+        ]);
+    }
+
+    #[test]
+    fn test_tar_archive_local() {
+        let root = std::env::temp_dir().join(format!("dionysius_tar_{}", std::process::id()));
+        let source = root.join("source");
+        std::fs::create_dir_all(source.join("child")).unwrap();
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        std::fs::write(source.join("kept.txt"), "kept").unwrap();
+        std::fs::write(source.join("child/file.txt"), "child").unwrap();
+        std::fs::write(source.join("src/main.o"), "object").unwrap();
+        std::fs::write(source.join("notes[1]"), "excluded").unwrap();
+        std::fs::write(source.join("notes1"), "kept").unwrap();
+        // left by an earlier run
+        std::fs::create_dir_all(source.join("archives")).unwrap();
+        std::fs::write(source.join("archives/earlier.tar.gz"), "earlier").unwrap();
+
+        let target = TarTargetConfig {
+            mode: Some("path".to_string()),
+            target: Some("archives".to_string()),
+            name: Some("{name}".to_string()),
+            compression: Some(TarCompression::Gzip),
+        };
+        let task = TarArchiveTask::new(
+            source.clone(),
+            &root,
+            &target,
+            vec![source.join("child"), source.join("notes[1]")],
+            vec![BorgPattern::Shell("**/*.o".to_string())],
+        ).unwrap();
+        assert_eq!(task.output, source.join("archives/source.tar.gz"));
+        task.execute(&mut None).unwrap();
+
+        let listing = Command::new("tar").arg("--list").arg("--gzip").arg("--file").arg(&task.output).output().unwrap();
+        let listing = String::from_utf8_lossy(&listing.stdout);
+        assert!(listing.lines().any(|l| l == "./kept.txt"));
+        assert!(listing.lines().any(|l| l == "./notes1"));
+        assert!(!listing.contains("notes[1]"));
+        assert!(!listing.contains("child"));
+        assert!(!listing.contains("main.o"));
+        assert!(!listing.contains("archives"));
+
+        let target = TarTargetConfig { target: Some(".".to_string()), ..target };
+        assert!(TarArchiveTask::new(source.clone(), &root, &target, vec![], vec![]).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
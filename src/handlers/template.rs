@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use chrono::format::{Fixed, Item, Numeric, Pad, StrftimeItems};
+use chrono::{DateTime, Local};
+
+// *************************************************************************** //
+// Name Templates
+// *************************************************************************** //
+
+/// Values substituted into `{placeholder}`s of a name template.
+///
+/// Besides the plain variables, `{now:FORMAT}` expands to the timestamp of the
+/// context formatted by a `strftime`-like `FORMAT`.
+#[derive(Debug, Clone)]
+pub struct TemplateContext {
+    pub vars: HashMap<&'static str, String>,
+    pub now: DateTime<Local>,
+}
+
+impl TemplateContext {
+    /// Context for a task working on `source` found under the traversal `root`, with `name`,
+    /// `path` (`source` relative to `root`, `/` replaced by `-`), `hostname`, `user`, `date` and `time`.
+    pub fn for_source(source: &Path, root: &Path) -> Self {
+        TemplateContext::at(source, root, Local::now())
+    }
+
+    /// Context as `for_source`, at the time `now`.
+    pub fn at(source: &Path, root: &Path, now: DateTime<Local>) -> Self {
+        let mut vars = HashMap::new();
+        let name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let path = source.strip_prefix(root)
+            .ok()
+            .map(|relative| relative.iter().map(|c| c.to_string_lossy()).collect::<Vec<_>>().join("-"))
+            .filter(|relative| !relative.is_empty())
+            .unwrap_or_else(|| name.clone());
+        vars.insert("name", name);
+        vars.insert("path", path);
+        vars.insert("hostname", hostname());
+        vars.insert("user", std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string()));
+        vars.insert("date", now.format("%Y-%m-%d").to_string());
+        vars.insert("time", now.format("%H%M%S").to_string());
+        TemplateContext { vars, now }
+    }
+
+    /// Check that `template` is well-formed and uses only known placeholders.
+    pub fn check(template: &str) -> Result<(), String> {
+        TemplateContext::for_source(Path::new("/"), Path::new("/")).expand(template).map(|_| ())
+    }
+
+    pub fn expand(&self, template: &str) -> Result<String, String> {
+        self.expand_with(template, false)
+    }
+
+    /// Expand `template` into a glob which matches the expansions of every run, and nothing
+    /// else: the time-dependent placeholders become fixed-width patterns like `[0-9][0-9]`, so
+    /// that e.g. the archives of `src/project` are not matched by those of `src/project-foo`.
+    /// Formats without a fixed width, such as month names, are refused.
+    pub fn expand_glob(&self, template: &str) -> Result<String, String> {
+        self.expand_with(template, true)
+    }
+
+    fn expand_with(&self, template: &str, time_as_glob: bool) -> Result<String, String> {
+        let mut result = String::new();
+        let mut rest = template;
+        let literal = |text: &str| if time_as_glob { escape_glob(text) } else { text.to_string() };
+        while let Some(start) = rest.find('{') {
+            result.push_str(&literal(&rest[..start]));
+            let end = rest[start..].find('}').ok_or_else(|| format!("Unclosed `{{` in template `{}`", template))?;
+            let placeholder = &rest[start + 1..start + end];
+            if let Some(format) = placeholder.strip_prefix("now:") {
+                let items = strftime_items(format, template)?;
+                if time_as_glob {
+                    for item in items.iter() {
+                        result.push_str(&glob_item(item).ok_or_else(|| format!(
+                            "`{{now:{}}}` in template `{}` has no fixed width to match archives by", format, template
+                        ))?);
+                    }
+                } else {
+                    write!(result, "{}", self.now.format_with_items(items.iter()))
+                        .map_err(|_| format!("Cannot format `{{now:{}}}` in template `{}`", format, template))?;
+                }
+            } else if time_as_glob && placeholder == "date" {
+                result.push_str(&digits(4));
+                result.push('-');
+                result.push_str(&digits(2));
+                result.push('-');
+                result.push_str(&digits(2));
+            } else if time_as_glob && placeholder == "time" {
+                result.push_str(&digits(6));
+            } else if let Some(value) = self.vars.get(placeholder) {
+                result.push_str(&literal(value));
+            } else {
+                return Err(format!("Unknown placeholder `{{{}}}` in template `{}`", placeholder, template));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(&literal(rest));
+        Ok(result)
+    }
+}
+
+/// The items of the `strftime`-like `format` of `{now:FORMAT}`, rejecting unknown specifiers.
+fn strftime_items<'a>(format: &'a str, template: &str) -> Result<Vec<Item<'a>>, String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        return Err(format!("Invalid time format `{}` in template `{}`", format, template));
+    }
+    Ok(items)
+}
+
+/// `text` with the glob metacharacters `*`, `?` and `[` matching only themselves.
+fn escape_glob(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            },
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A glob matching `width` digits.
+fn digits(width: usize) -> String {
+    "[0-9]".repeat(width)
+}
+
+/// A glob matching every formatting of `item`, `None` if its width varies.
+fn glob_item(item: &Item) -> Option<String> {
+    use Numeric::*;
+    match item {
+        Item::Literal(text) | Item::Space(text) => Some(escape_glob(text)),
+        Item::OwnedLiteral(text) | Item::OwnedSpace(text) => Some(escape_glob(text)),
+        Item::Numeric(numeric, pad) => {
+            let width = match numeric {
+                Year | IsoYear => 4,
+                YearDiv100 | YearMod100 | IsoYearDiv100 | IsoYearMod100 | Month | Day
+                    | WeekFromSun | WeekFromMon | IsoWeek | Hour | Hour12 | Minute | Second => 2,
+                Ordinal => 3,
+                Nanosecond => 9,
+                Quarter | NumDaysFromSun | WeekdayFromMon => 1,
+                _ => return None,
+            };
+            match pad {
+                _ if width == 1 => Some(digits(1)),
+                Pad::Zero => Some(digits(width)),
+                Pad::Space => Some(format!("{}{}", "[ 0-9]".repeat(width - 1), digits(1))),
+                Pad::None => None,
+            }
+        },
+        Item::Fixed(Fixed::UpperAmPm) => Some("[AP]M".to_string()),
+        Item::Fixed(Fixed::LowerAmPm) => Some("[ap]m".to_string()),
+        Item::Fixed(Fixed::Nanosecond3) => Some(format!(".{}", digits(3))),
+        Item::Fixed(Fixed::Nanosecond6) => Some(format!(".{}", digits(6))),
+        Item::Fixed(Fixed::Nanosecond9) => Some(format!(".{}", digits(9))),
+        Item::Fixed(Fixed::TimezoneOffset) => Some(format!("[+-]{}", digits(4))),
+        Item::Fixed(Fixed::TimezoneOffsetColon) => Some(format!("[+-]{}:{}", digits(2), digits(2))),
+        _ => None,
+    }
+}
+
+fn hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn fixed_context() -> TemplateContext {
+        let mut vars = HashMap::new();
+        vars.insert("name", "project".to_string());
+        vars.insert("hostname", "laptop".to_string());
+        TemplateContext {
+            vars,
+            now: Local.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let context = fixed_context();
+        assert_eq!(context.expand("{hostname}-{name}").unwrap(), "laptop-project");
+        assert_eq!(context.expand("{name}-{now:%Y%m%dT%H%M%S}").unwrap(), "project-20250102T030405");
+        assert_eq!(context.expand("plain").unwrap(), "plain");
+        assert_eq!(context.expand_glob("{hostname}-{name}-{now:%Y}").unwrap(), "laptop-project-[0-9][0-9][0-9][0-9]");
+        assert_eq!(context.expand_glob("{name}-{date}T{now:%H:%M}").unwrap(), format!(
+            "project-{0}{0}{0}{0}-{0}{0}-{0}{0}T{0}{0}:{0}{0}", "[0-9]"
+        ));
+        assert_eq!(context.expand_glob("{name}[1]*-{now:%e}").unwrap(), "project[[]1][*]-[ 0-9][0-9]");
+        // month names and unpadded numbers vary in width
+        assert!(context.expand_glob("{name}-{now:%b}").is_err());
+        assert!(context.expand_glob("{name}-{now:%-d}").is_err());
+    }
+
+    #[test]
+    fn test_template_path() {
+        let context = TemplateContext::for_source(Path::new("/home/me/src/project"), Path::new("/home/me"));
+        assert_eq!(context.expand("{path}").unwrap(), "src-project");
+        let context = TemplateContext::for_source(Path::new("/home/me"), Path::new("/home/me"));
+        assert_eq!(context.expand("{path}").unwrap(), "me");
+    }
+
+    #[test]
+    fn test_expand_template_errors() {
+        let context = fixed_context();
+        assert!(context.expand("{unknown}").is_err());
+        assert!(context.expand("{name").is_err());
+        assert!(context.expand("{now:%Q}").is_err());
+        assert!(context.expand_glob("{now:%Q}").is_err());
+        assert!(TemplateContext::check("{name}-{now:%Y%").is_err());
+    }
+}
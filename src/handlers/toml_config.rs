@@ -6,7 +6,7 @@ use serde::Deserialize;
 use strum::VariantNames;
 use toml::Table;
 
-use super::{borg::BorgConfig, git::GitConfig, trigger::TriggerConfig};
+use super::{borg::BorgConfig, git::GitConfig, restic::ResticConfig, rsync::RsyncConfig, tar::TarConfig, trigger::TriggerConfig};
 
 
 // *************************************************************************** //
@@ -19,6 +19,9 @@ pub struct DionysiusConfig {
     pub trigger: Option<PushTaskConfig>,
     pub git: Option<PushTaskConfig>,
     pub borg: Option<PushTaskConfig>,
+    pub rsync: Option<PushTaskConfig>,
+    pub tar: Option<PushTaskConfig>,
+    pub restic: Option<PushTaskConfig>,
     // pub ntfs: Option<NTFSConfig>,
 }
 // #[derive(Debug, Deserialize, Reflect)]
@@ -43,10 +46,14 @@ impl std::fmt::Display for DionysiusConfig {
 
 #[derive(Debug, Clone, Deserialize, Reflect)]
 #[derive(strum_macros::VariantNames)]
+#[allow(clippy::large_enum_variant)] // short-lived, one per directory
 pub enum PushTaskConfig {
     Trigger(TriggerConfig),
     Git(GitConfig),
-    Borg(BorgConfig)
+    Borg(BorgConfig),
+    Rsync(RsyncConfig),
+    Tar(TarConfig),
+    Restic(ResticConfig),
 }
 //TODO: make an unwrap macro
 impl PushTaskConfig {
@@ -74,6 +81,24 @@ impl PushTaskConfig {
             _ => None
         }
     }
+    pub fn get_rsync(&self) -> Option<&RsyncConfig> {
+        match self {
+            PushTaskConfig::Rsync(rsync_config) => Some(rsync_config),
+            _ => None
+        }
+    }
+    pub fn get_tar(&self) -> Option<&TarConfig> {
+        match self {
+            PushTaskConfig::Tar(tar_config) => Some(tar_config),
+            _ => None
+        }
+    }
+    pub fn get_restic(&self) -> Option<&ResticConfig> {
+        match self {
+            PushTaskConfig::Restic(restic_config) => Some(restic_config),
+            _ => None
+        }
+    }
 
     pub fn accepted_trigger(&self) -> Vec<String> {
         match self {
@@ -95,8 +120,35 @@ impl PushTaskConfig {
                     .clone()
                     .unwrap()
             },
+            PushTaskConfig::Rsync(rsync_config) => {
+                rsync_config
+                    .assets
+                    .as_ref()
+                    .unwrap()
+                    .trigger_by
+                    .clone()
+                    .unwrap()
+            },
+            PushTaskConfig::Tar(tar_config) => {
+                tar_config
+                    .assets
+                    .as_ref()
+                    .unwrap()
+                    .trigger_by
+                    .clone()
+                    .unwrap()
+            },
+            PushTaskConfig::Restic(restic_config) => {
+                restic_config
+                    .assets
+                    .as_ref()
+                    .unwrap()
+                    .trigger_by
+                    .clone()
+                    .unwrap()
+            },
             PushTaskConfig::Trigger(_) => {
-                vec!["git".to_string(), "borg".to_string()]
+                vec!["git".to_string(), "borg".to_string(), "rsync".to_string(), "tar".to_string(), "restic".to_string()]
             }
         }
     }
@@ -116,6 +168,24 @@ impl PushTaskConfig {
                     .as_ref()
                     .and_then(|conf| conf.on_recursion.clone())
             },
+            PushTaskConfig::Rsync(rsync_config) => {
+                rsync_config
+                    .heritage
+                    .as_ref()
+                    .and_then(|conf| conf.on_recursion.clone())
+            },
+            PushTaskConfig::Tar(tar_config) => {
+                tar_config
+                    .heritage
+                    .as_ref()
+                    .and_then(|conf| conf.on_recursion.clone())
+            },
+            PushTaskConfig::Restic(restic_config) => {
+                restic_config
+                    .heritage
+                    .as_ref()
+                    .and_then(|conf| conf.on_recursion.clone())
+            },
             PushTaskConfig::Trigger(_) => {
                 Some(OnRecursion::Inherit)
             }
@@ -143,6 +213,33 @@ impl PushTaskConfig {
                     .as_mut()
                     .expect("You should have this after completion")
             },
+            PushTaskConfig::Rsync(rsync_config) => {
+                rsync_config
+                    .heritage
+                    .as_mut()
+                    .expect("You should have this after completion")
+                    .on_recursion
+                    .as_mut()
+                    .expect("You should have this after completion")
+            },
+            PushTaskConfig::Tar(tar_config) => {
+                tar_config
+                    .heritage
+                    .as_mut()
+                    .expect("You should have this after completion")
+                    .on_recursion
+                    .as_mut()
+                    .expect("You should have this after completion")
+            },
+            PushTaskConfig::Restic(restic_config) => {
+                restic_config
+                    .heritage
+                    .as_mut()
+                    .expect("You should have this after completion")
+                    .on_recursion
+                    .as_mut()
+                    .expect("You should have this after completion")
+            },
             PushTaskConfig::Trigger(_) => {
                 unreachable!()
             }
@@ -193,6 +290,23 @@ pub trait CompletableConfig {
     fn completion(&self) -> Self::CompletionResult;
 }
 
+/// Whether none of the listed `Option` fields of `$obj` is `None`, for `is_complete`.
+macro_rules! check_fields {
+    ($obj:expr, $($field:ident),+) => {
+        {
+            let mut complete = true;
+            $(
+                if $obj.$field.is_none() {
+                    complete = false;
+                }
+            )+
+            complete
+        }
+    };
+}
+
+pub(crate) use check_fields;
+
 impl CompletableConfig for DionysiusConfig {
     type CompletionResult = Result<Self, &'static str>;
 
@@ -229,6 +343,15 @@ impl CompletableConfig for PushTaskConfig {
             },
             PushTaskConfig::Borg(borg_config) => {
                 borg_config.is_complete()
+            },
+            PushTaskConfig::Rsync(rsync_config) => {
+                rsync_config.is_complete()
+            },
+            PushTaskConfig::Tar(tar_config) => {
+                tar_config.is_complete()
+            },
+            PushTaskConfig::Restic(restic_config) => {
+                restic_config.is_complete()
             }
         }
     }
@@ -246,6 +369,15 @@ impl CompletableConfig for PushTaskConfig {
                 PushTaskConfig::Borg(borg_config) => {
                     Self::Borg(borg_config.completion()?)
                 },
+                PushTaskConfig::Rsync(rsync_config) => {
+                    Self::Rsync(rsync_config.completion()?)
+                },
+                PushTaskConfig::Tar(tar_config) => {
+                    Self::Tar(tar_config.completion()?)
+                },
+                PushTaskConfig::Restic(restic_config) => {
+                    Self::Restic(restic_config.completion()?)
+                },
             }
         )
     }
@@ -275,6 +407,18 @@ pub trait InheritableConfig {
     fn inherit_from(&self, other: Option<&Self>) -> Self;
 }
 
+/// `on_recursion` with `inherit` taken from the super config, or the default without one.
+pub fn inherit_on_recursion(on_recursion: &Option<OnRecursion>, super_on_recursion: Option<&Option<OnRecursion>>) -> Option<OnRecursion> {
+    match on_recursion {
+        Some(OnRecursion::Inherit) => match super_on_recursion {
+            Some(super_on_recursion) => super_on_recursion.clone(),
+            None => Some(OnRecursion::default()),
+        },
+        None => unreachable!(),
+        on_recursion => on_recursion.clone(),
+    }
+}
+
 pub trait HasInheritableConfig {
     type M: InheritableConfig;
 
@@ -358,7 +502,7 @@ pub fn load_raw_config(file_path: &Path) -> Result<DionysiusConfig, Box<dyn std:
         let completed = config.completion();
         match completed {
             Ok(completed) => Ok(completed),
-            Err(_) => Err("Config is still not complete after trying completion.".into())
+            Err(e) => Err(format!("Config is still not complete after trying completion: {}", e).into())
         }
     }
 }
@@ -1,10 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bevy_reflect::Reflect;
 use serde::Deserialize;
 
 use crate::handlers::toml_config::{InheritableConfig, HasInheritableConfig};
-use crate::task::PushTask;
+use crate::task::{PushTask, TaskError, TaskOutcome};
 
 use super::toml_config::OnRecursion;
 
@@ -43,15 +43,25 @@ impl HasInheritableConfig for TriggerConfig {
 	}
 }
 impl PushTask for TriggerTask {
-	fn execute(&self, command_list: &mut Option<Vec<String>>) {
+	fn execute(&self, _command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError> {
 		// do nothing now.
+		Ok(TaskOutcome::Skipped("trigger has no action".to_string()))
 	}
-	fn exclude_pattern_options(&self) -> Vec<String> {
-		unreachable!()
+	fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError> {
+		// nothing is archived, so nothing is excluded
+		Ok(Vec::new())
 	}
 
 	fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 		println!("Trigger: {:?}", self.current_dir);
 		Ok(())
 	}
+
+	fn kind(&self) -> &'static str {
+		"trigger"
+	}
+
+	fn source(&self) -> &Path {
+		&self.current_dir
+	}
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::task::{PushTask, TaskError, TaskOutcome};
+
+/// Where dionysius keeps what it remembers between runs: `$XDG_STATE_HOME/dionysius`.
+pub fn state_dir() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("dionysius")
+}
+
+/// Longest file name `state_file_name` returns, well below the usual limit of 255 bytes.
+const MAX_STATE_FILE_NAME: usize = 200;
+
+/// `key` as a file name, distinct for distinct keys: ASCII letters, digits, `-` and `.` are
+/// kept, every other byte is written as `_` and two hex digits, e.g. `/a_b` as `_2Fa_5Fb`.
+/// Names too long for the file system are cut and end in `~` and a hash of the whole key.
+pub fn state_file_name(key: &str) -> String {
+    let mut name = String::new();
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("_{:02X}", byte));
+        }
+    }
+    if name.len() > MAX_STATE_FILE_NAME {
+        name.truncate(MAX_STATE_FILE_NAME - 17);
+        name.push_str(&format!("~{:016x}", fnv1a(key.as_bytes())));
+    }
+    name
+}
+
+/// The 64-bit FNV-1a hash, stable across builds unlike `std::hash`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Replace `path` with `content` through a temporary file next to it, so that a crash leaves
+/// either the old or the new content.
+pub fn write_state_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let dir = path.parent().ok_or_else(|| std::io::Error::other("State file has no directory"))?;
+    std::fs::create_dir_all(dir)?;
+    let file_name = path.file_name().ok_or_else(|| std::io::Error::other("State file has no name"))?;
+    let temp = dir.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+/// The last run of one task, keyed by its kind and source.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub kind: String,
+    pub source: PathBuf,
+    pub last_attempt: Option<DateTime<Local>>,
+    /// `succeeded`, `skipped: <reason>` or `failed: <error>`.
+    pub last_result: Option<String>,
+    pub last_success: Option<DateTime<Local>>,
+    /// Of the last attempt, in seconds.
+    pub duration: Option<f64>,
+    /// The commit HEAD was at after the last successful git push.
+    pub pushed_commit: Option<String>,
+    /// The last archive borg created.
+    pub archive: Option<String>,
+}
+
+impl JournalEntry {
+    /// Time since the last success, `None` if there was none.
+    pub fn age(&self) -> Option<TimeDelta> {
+        self.last_success.map(|time| Local::now().signed_duration_since(time))
+    }
+
+    /// Whether the source was not backed up successfully within `max_age`, or never.
+    pub fn is_stale(entry: Option<&JournalEntry>, max_age: TimeDelta) -> bool {
+        entry.and_then(JournalEntry::age).is_none_or(|age| age > max_age)
+    }
+
+    /// e.g. `last backed up 3d ago`.
+    pub fn describe_age(entry: Option<&JournalEntry>) -> String {
+        match entry.and_then(JournalEntry::age) {
+            Some(age) => format!("last backed up {} ago", format_age(age)),
+            None => "never backed up".to_string(),
+        }
+    }
+}
+
+/// One TOML file per task under `<state dir>/journal/<kind>/`.
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf) -> Self {
+        Journal { dir }
+    }
+
+    pub fn default_location() -> Self {
+        Journal::new(state_dir().join("journal"))
+    }
+
+    fn path(&self, kind: &str, source: &Path) -> PathBuf {
+        self.dir.join(kind).join(format!("{}.toml", state_file_name(&source.to_string_lossy())))
+    }
+
+    /// The recorded entry, `None` if the task never ran or its entry is unreadable.
+    pub fn load(&self, kind: &str, source: &Path) -> Option<JournalEntry> {
+        std::fs::read_to_string(self.path(kind, source))
+            .ok()
+            .and_then(|content| toml::from_str::<JournalEntry>(&content).ok())
+    }
+
+    /// Record a real run of `task`, started at `started` and taking `duration`.
+    pub fn record(
+        &self,
+        task: &dyn PushTask,
+        started: DateTime<Local>,
+        duration: Duration,
+        result: &Result<TaskOutcome, TaskError>,
+    ) -> std::io::Result<()> {
+        let mut entry = self.load(task.kind(), task.source()).unwrap_or_else(|| JournalEntry {
+            kind: task.kind().to_string(),
+            source: task.source().to_path_buf(),
+            ..Default::default()
+        });
+        entry.last_attempt = Some(started);
+        entry.duration = Some(duration.as_secs_f64());
+        entry.last_result = Some(match result {
+            Ok(TaskOutcome::Succeeded) => "succeeded".to_string(),
+            Ok(TaskOutcome::Skipped(reason)) => format!("skipped: {}", reason),
+            Err(err) => format!("failed: {}", err),
+        });
+        if matches!(result, Ok(TaskOutcome::Succeeded)) {
+            entry.last_success = Some(started);
+            task.journal(&mut entry);
+        }
+
+        write_state_file(&self.path(task.kind(), task.source()), &toml::to_string(&entry).map_err(std::io::Error::other)?)
+    }
+}
+
+/// e.g. `5m`, `3h`, `2d`, `6w`, `1y`.
+pub fn format_age(age: TimeDelta) -> String {
+    let seconds = age.num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        86400..1209600 => format!("{}d", seconds / 86400),
+        1209600..31536000 => format!("{}w", seconds / 604800),
+        _ => format!("{}y", seconds / 31536000),
+    }
+}
+
+/// The inverse of `format_age`: a number with an `s`, `m`, `h`, `d`, `w` or `y` suffix.
+pub fn parse_age(age: &str) -> Option<TimeDelta> {
+    let age = age.trim();
+    let unit = age.chars().last()?;
+    let number: i64 = age[..age.len() - unit.len_utf8()].trim().parse().ok()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 604800,
+        'y' => 31536000,
+        _ => return None,
+    };
+    TimeDelta::try_seconds(number.checked_mul(seconds)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::trigger::TriggerTask;
+
+    #[test]
+    fn test_format_and_parse_age() {
+        assert_eq!(format_age(TimeDelta::seconds(-5)), "0s");
+        assert_eq!(format_age(TimeDelta::seconds(59)), "59s");
+        assert_eq!(format_age(TimeDelta::minutes(90)), "1h");
+        assert_eq!(format_age(TimeDelta::days(3)), "3d");
+        assert_eq!(format_age(TimeDelta::days(30)), "4w");
+        assert_eq!(format_age(TimeDelta::days(800)), "2y");
+        assert_eq!(parse_age("7d"), Some(TimeDelta::days(7)));
+        assert_eq!(parse_age("12h"), Some(TimeDelta::hours(12)));
+        assert_eq!(parse_age("d"), None);
+        assert_eq!(parse_age("7x"), None);
+    }
+
+    #[test]
+    fn test_record_and_staleness() {
+        let dir = std::env::temp_dir().join(format!("dionysius_journal_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let journal = Journal::new(dir.clone());
+        let task = TriggerTask { current_dir: PathBuf::from("/src/project") };
+        assert_eq!(journal.load("trigger", &task.current_dir), None);
+        assert!(JournalEntry::is_stale(None, TimeDelta::days(7)));
+
+        let started = Local::now() - TimeDelta::days(3);
+        journal.record(&task, started, Duration::from_secs(2), &Ok(TaskOutcome::Succeeded)).unwrap();
+        journal.record(&task, Local::now(), Duration::from_secs(1), &Ok(TaskOutcome::Skipped("busy".to_string()))).unwrap();
+
+        let entry = journal.load("trigger", &task.current_dir).unwrap();
+        assert_eq!(entry.last_success.map(|time| time.timestamp()), Some(started.timestamp()));
+        assert_eq!(entry.last_result.as_deref(), Some("skipped: busy"));
+        assert_eq!(entry.duration, Some(1.0));
+        assert_eq!(JournalEntry::describe_age(Some(&entry)), "last backed up 3d ago");
+        assert!(!JournalEntry::is_stale(Some(&entry), TimeDelta::days(7)));
+        assert!(JournalEntry::is_stale(Some(&entry), TimeDelta::days(1)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_state_file_names_are_distinct() {
+        assert_eq!(state_file_name("/a/b"), "_2Fa_2Fb");
+        assert_eq!(state_file_name("/a_b"), "_2Fa_5Fb");
+        assert_eq!(state_file_name("ssh://host/repo-1.git"), "ssh_3A_2F_2Fhost_2Frepo-1.git");
+        let long = "/x".repeat(200);
+        assert_eq!(state_file_name(&long).len(), MAX_STATE_FILE_NAME);
+        assert_ne!(state_file_name(&long), state_file_name(&format!("{}/y", long)));
+
+        let dir = std::env::temp_dir().join(format!("dionysius_journal_names_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let journal = Journal::new(dir.clone());
+        let first = TriggerTask { current_dir: PathBuf::from("/a/b") };
+        let second = TriggerTask { current_dir: PathBuf::from("/a_b") };
+        journal.record(&first, Local::now(), Duration::from_secs(1), &Ok(TaskOutcome::Succeeded)).unwrap();
+        journal.record(&second, Local::now(), Duration::from_secs(1), &Ok(TaskOutcome::Succeeded)).unwrap();
+        assert!(journal.load("trigger", &first.current_dir).is_some());
+        assert!(journal.load("trigger", &second.current_dir).is_some());
+        // no temporary files left behind
+        assert_eq!(std::fs::read_dir(dir.join("trigger")).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,31 +1,132 @@
-pub enum LogLevel {
-	Info,
-	Warn,
-	Error,
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{filter::Targets, fmt, prelude::*, Layer, Registry};
+
+use crate::task::PushTask;
+
+/// Rotated log files kept next to the current one.
+const MAX_LOG_FILES: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+	Text,
+	/// One JSON object per event, with the fields of its spans.
+	Json,
 }
 
-pub fn log(level: LogLevel, message: &str) {
-	match level {
-		LogLevel::Info => eprintln!("INFO: {}", message),
-		LogLevel::Warn => eprintln!("WARN: {}", message),
-		LogLevel::Error => eprintln!("ERROR: {}", message),
+/// Where and how much to log, from `-v`, `-q`, `--log-file`, `--log-rotation` and `--log-format`.
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+	pub level: LevelFilter,
+	pub format: LogFormat,
+	/// Also log to this file, rotated with `rotation`.
+	pub file: Option<PathBuf>,
+	pub rotation: Rotation,
+}
+
+impl Default for LogOptions {
+	fn default() -> Self {
+		LogOptions {
+			level: LevelFilter::INFO,
+			format: LogFormat::Text,
+			file: None,
+			rotation: Rotation::DAILY,
+		}
+	}
+}
+
+/// `info` by default, each `-v` one level more and each `-q` one level less, down to `error`.
+pub fn level_from_counts(verbose: u8, quiet: u8) -> LevelFilter {
+	match verbose as i16 - quiet as i16 {
+		i16::MIN..=-2 => LevelFilter::ERROR,
+		-1 => LevelFilter::WARN,
+		0 => LevelFilter::INFO,
+		1 => LevelFilter::DEBUG,
+		_ => LevelFilter::TRACE,
+	}
+}
+
+/// Install the global subscriber: stderr, and the log file if any. Logs of other crates are
+/// left out.
+pub fn init(options: &LogOptions) -> std::io::Result<()> {
+	let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+
+	let stderr = fmt::layer()
+		.with_writer(std::io::stderr)
+		.with_target(false)
+		.with_ansi(std::io::stderr().is_terminal());
+	layers.push(match options.format {
+		LogFormat::Text => stderr.without_time().boxed(),
+		LogFormat::Json => stderr.json().with_current_span(false).boxed(),
+	});
+
+	if let Some(file) = &options.file {
+		let directory = file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+		let prefix = file.file_name().ok_or_else(|| std::io::Error::other("Log file has no name"))?;
+		std::fs::create_dir_all(directory)?;
+		let appender = RollingFileAppender::builder()
+			.rotation(options.rotation.clone())
+			.filename_prefix(prefix.to_string_lossy())
+			.max_log_files(MAX_LOG_FILES)
+			.build(directory)
+			.map_err(std::io::Error::other)?;
+		let file = fmt::layer().with_writer(appender).with_target(false).with_ansi(false);
+		layers.push(match options.format {
+			LogFormat::Text => file.boxed(),
+			LogFormat::Json => file.json().with_current_span(false).boxed(),
+		});
 	}
+
+	let filter = Targets::new().with_target(env!("CARGO_CRATE_NAME"), options.level);
+	tracing_subscriber::registry()
+		.with(layers.with_filter(filter))
+		.try_init()
+		.map_err(std::io::Error::other)
+}
+
+/// The span events of `task` are logged in, carrying its kind and directory.
+pub fn task_span(task: &dyn PushTask) -> tracing::Span {
+	tracing::info_span!("task", kind = %task.kind(), source = %task.source().display())
 }
 
-// macro_rules! log_info {
-// 	($message:expr) => {
-// 		log(LogLevel::Info, $message);
-// 	};
-// }
+macro_rules! log_debug {
+	($($arg:tt)+) => {
+		tracing::debug!($($arg)+)
+	};
+}
 
-// macro_rules! log_warn {
-// 	($message:expr) => {
-// 		log(LogLevel::Warn, $message);
-// 	};
-// }
+macro_rules! log_info {
+	($($arg:tt)+) => {
+		tracing::info!($($arg)+)
+	};
+}
+
+macro_rules! log_warn {
+	($($arg:tt)+) => {
+		tracing::warn!($($arg)+)
+	};
+}
 
-// macro_rules! log_error {
-// 	($message:expr) => {
-// 		log(LogLevel::Error, $message);
-// 	};
-// }
\ No newline at end of file
+macro_rules! log_error {
+	($($arg:tt)+) => {
+		tracing::error!($($arg)+)
+	};
+}
+
+pub(crate) use {log_debug, log_error, log_info, log_warn};
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_level_from_counts() {
+		assert_eq!(level_from_counts(0, 0), LevelFilter::INFO);
+		assert_eq!(level_from_counts(1, 0), LevelFilter::DEBUG);
+		assert_eq!(level_from_counts(3, 0), LevelFilter::TRACE);
+		assert_eq!(level_from_counts(0, 1), LevelFilter::WARN);
+		assert_eq!(level_from_counts(0, 5), LevelFilter::ERROR);
+	}
+}
@@ -4,10 +4,17 @@ mod cli;
 mod handlers;
 mod task;
 mod log;
+mod journal;
+mod executor;
+mod traversal;
 
 #[tokio::main]
 async fn main() {
     let matches = cli::entry::main_cli().get_matches();
+    if let Err(err) = log::init(&cli::entry::log_options(&matches)) {
+        eprintln!("Failed to set up logging: {}", err);
+        std::process::exit(1);
+    }
 
     match matches.subcommand() {
         Some(("test", _)) => {
@@ -18,12 +25,16 @@ async fn main() {
                 Some(("trigger", sub2)) => cli::push::push_main(sub_matches, sub2, "trigger").await,
                 Some(("git", sub2)) => cli::push::push_main(sub_matches, sub2, "git").await,
                 Some(("borg", sub2)) => cli::push::push_main(sub_matches, sub2, "borg").await,
+                Some(("rsync", sub2)) => cli::push::push_main(sub_matches, sub2, "rsync").await,
+                Some(("tar", sub2)) => cli::push::push_main(sub_matches, sub2, "tar").await,
+                Some(("restic", sub2)) => cli::push::push_main(sub_matches, sub2, "restic").await,
                 _ => {
                     eprintln!("Unknown subcommand");
                     unreachable!()
                 }
             }
         },
+        Some(("status", sub_matches)) => cli::status::status_main(sub_matches).await,
         // Some(("ls", sub_matches)) => {
         //     cli::entry::set_threads(&matches);
         //     cli::list::list_main(&sub_matches);
@@ -1,18 +1,20 @@
-use core::panic;
-use std::{path::PathBuf, sync::{Arc, Mutex}};
-use tokio;
+use std::{fmt, path::{Path, PathBuf}, sync::{Arc, Mutex}};
 use async_recursion::async_recursion;
 use futures::future::join_all;
-use walkdir::WalkDir;
+use serde::Serialize;
 
 use crate::{
     handlers::{
-        borg::{BorgCreateOptions, BorgCreateTask},
+        borg::{BorgCreateTask, BorgError, BorgTaskStatus},
         exclude::{BorgPattern, GitIgnorePattern},
-        git::GitSaveTask,
+        git::{recorded_borg_excludes, GitCommitOptions, GitError, GitGuard, GitPushTarget, GitRepoStatus, GitSaveTask, OnUnsave},
+        restic::{ResticBackupTask, ResticError},
+        rsync::{RsyncError, RsyncTask},
+        tar::{TarArchiveTask, TarError},
         toml_config::{load_config, DionysiusConfig, HasInheritableConfig, OnRecursion, PushTaskConfig},
         trigger::TriggerTask
-    }, log::{log, LogLevel}
+    }, journal::{Journal, JournalEntry}, log::{log_error, log_warn},
+    traversal::{Cancelled, Traversal}
 };
 
 // *************************************************************************** //
@@ -20,25 +22,330 @@ use crate::{
 // *************************************************************************** //
 
 pub trait PushTask where Self: std::fmt::Debug {
-    // should return `Result` in future for handling errors.
-	fn execute(&self, command_list: &mut Option<Vec<String>>);
-	fn exclude_pattern_options(&self) -> Vec<String>;
+	fn execute(&self, command_list: &mut Option<Vec<String>>) -> Result<TaskOutcome, TaskError>;
+	fn exclude_pattern_options(&self) -> Result<Vec<String>, TaskError>;
     // Dev Note: pretend or preview?
     fn preview(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// Short name of the backend, used in run summaries.
+    fn kind(&self) -> &'static str;
+    /// The directory this task operates on.
+    fn source(&self) -> &Path;
+    /// Where this task pushes to, as shown to the user.
+    fn target(&self) -> Option<String> {
+        None
+    }
+    /// What `--format json` reports about this task before it runs.
+    fn describe(&self) -> TaskDescriptor {
+        TaskDescriptor::new(self)
+    }
+    /// A description of the unsaved work this task needs a decision on before running,
+    /// e.g. for `on_unsave = "ask"`.
+    fn review(&self) -> Option<String> {
+        None
+    }
+    /// The decision on `review`: whether to save, or `None` if nobody could be asked.
+    fn resolve(&mut self, _answer: Option<bool>) {}
+    /// Note what a successful run produced in its journal entry, e.g. the pushed commit.
+    fn journal(&self, _entry: &mut JournalEntry) {}
+    /// Something only one task may use at a time, e.g. a borg repository with its lock.
+    fn exclusive_resource(&self) -> Option<String> {
+        None
+    }
 }
 
 pub type TaskList = Vec<Box<dyn PushTask + Send>>;
 
+/// Version of the JSON emitted with `--format json|ndjson`, bumped whenever a field is
+/// removed or changes its meaning.
+pub const OUTPUT_VERSION: u32 = 1;
+
+/// A task as emitted with `--format json|ndjson`.
+#[derive(Debug, Serialize)]
+pub struct TaskDescriptor {
+    pub version: u32,
+    pub kind: &'static str,
+    pub source: PathBuf,
+    pub target: Option<String>,
+    pub exclude: Vec<String>,
+    /// Why `exclude` could not be determined, e.g. an invalid pattern.
+    pub exclude_error: Option<String>,
+    pub status: Option<TaskStatus>,
+    /// Commands of a dry run.
+    pub commands: Option<Vec<String>>,
+    /// Set once the task has run.
+    pub outcome: Option<ReportedOutcome>,
+    /// The previous run, `None` if it never ran.
+    pub journal: Option<JournalEntry>,
+    /// Not backed up within `--stale-after`, where given.
+    pub stale: Option<bool>,
+}
+
+impl TaskDescriptor {
+    pub fn new<T: PushTask + ?Sized>(task: &T) -> Self {
+        let (exclude, exclude_error) = match task.exclude_pattern_options() {
+            Ok(exclude) => (exclude, None),
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+        TaskDescriptor {
+            version: OUTPUT_VERSION,
+            kind: task.kind(),
+            source: task.source().to_path_buf(),
+            target: task.target(),
+            exclude,
+            exclude_error,
+            status: None,
+            commands: None,
+            outcome: None,
+            journal: Journal::default_location().load(task.kind(), task.source()),
+            stale: None,
+        }
+    }
+}
+
+/// Backend specific state of the source or target of a task.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Git(GitRepoStatus),
+    Borg(BorgTaskStatus),
+}
+
+/// `TaskOutcome` or the error of a task, as emitted with `--format json|ndjson`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "result", content = "reason")]
+pub enum ReportedOutcome {
+    Succeeded,
+    Skipped(String),
+    Failed(String),
+}
+
+impl From<&Result<TaskOutcome, TaskError>> for ReportedOutcome {
+    fn from(result: &Result<TaskOutcome, TaskError>) -> Self {
+        match result {
+            Ok(TaskOutcome::Succeeded) => ReportedOutcome::Succeeded,
+            Ok(TaskOutcome::Skipped(reason)) => ReportedOutcome::Skipped(reason.clone()),
+            Err(err) => ReportedOutcome::Failed(err.to_string()),
+        }
+    }
+}
+
+/// Outcome of a task which did not fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskOutcome {
+    Succeeded,
+    Skipped(String),
+}
+
+#[derive(Debug)]
+pub enum TaskError {
+    Git(GitError),
+    Borg(BorgError),
+    Rsync(RsyncError),
+    Tar(TarError),
+    Restic(ResticError),
+}
+
+impl From<GitError> for TaskError {
+    fn from(err: GitError) -> TaskError {
+        TaskError::Git(err)
+    }
+}
+
+impl From<BorgError> for TaskError {
+    fn from(err: BorgError) -> TaskError {
+        TaskError::Borg(err)
+    }
+}
+
+impl From<RsyncError> for TaskError {
+    fn from(err: RsyncError) -> TaskError {
+        TaskError::Rsync(err)
+    }
+}
+
+impl From<TarError> for TaskError {
+    fn from(err: TarError) -> TaskError {
+        TaskError::Tar(err)
+    }
+}
+
+impl From<ResticError> for TaskError {
+    fn from(err: ResticError) -> TaskError {
+        TaskError::Restic(err)
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Git(err) => write!(f, "{}", err),
+            TaskError::Borg(err) => write!(f, "{}", err),
+            TaskError::Rsync(err) => write!(f, "{}", err),
+            TaskError::Tar(err) => write!(f, "{}", err),
+            TaskError::Restic(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+pub struct TaskReport {
+    pub kind: &'static str,
+    pub source: PathBuf,
+    pub result: Result<TaskOutcome, TaskError>,
+}
+
+/// Outcomes of every task executed in one run.
+#[derive(Default)]
+pub struct RunSummary {
+    pub reports: Vec<TaskReport>,
+}
+
+impl RunSummary {
+    pub fn record(&mut self, task: &dyn PushTask, result: Result<TaskOutcome, TaskError>) {
+        self.reports.push(TaskReport {
+            kind: task.kind(),
+            source: task.source().to_path_buf(),
+            result,
+        });
+    }
+
+    pub fn succeeded_count(&self) -> usize {
+        self.reports.iter().filter(|r| matches!(r.result, Ok(TaskOutcome::Succeeded))).count()
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.reports.iter().filter(|r| matches!(r.result, Ok(TaskOutcome::Skipped(_)))).count()
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.reports.iter().filter(|r| r.result.is_err()).count()
+    }
+
+    pub fn has_failure(&self) -> bool {
+        self.failed_count() > 0
+    }
+
+    pub fn counts(&self) -> SummaryCounts {
+        SummaryCounts {
+            succeeded: self.succeeded_count(),
+            skipped: self.skipped_count(),
+            failed: self.failed_count(),
+        }
+    }
+}
+
+/// The totals of a `RunSummary`, as emitted with `--format json|ndjson`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SummaryCounts {
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.reports {
+            match &report.result {
+                Ok(TaskOutcome::Succeeded) => writeln!(f, "[ok]      {}: {}", report.kind, report.source.display())?,
+                Ok(TaskOutcome::Skipped(reason)) => writeln!(f, "[skipped] {}: {} ({})", report.kind, report.source.display(), reason)?,
+                Err(err) => writeln!(f, "[failed]  {}: {} ({})", report.kind, report.source.display(), err)?,
+            }
+        }
+        write!(
+            f,
+            "{} succeeded, {} skipped, {} failed.",
+            self.succeeded_count(),
+            self.skipped_count(),
+            self.failed_count()
+        )
+    }
+}
+
+/// How the questions of `PushTask::review` are answered, see `review_tasks`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnswerPolicy {
+    Yes,
+    No,
+    /// Ask on the terminal, or leave it to each task's unattended default without one.
+    Ask,
+}
+
 #[derive(Debug, Clone)]
 pub struct CliTaskConfig {
     pub exclude_patterns: Vec<String>,
     pub search_hidden: bool,
+    /// The directory given on the command line, where the traversal starts.
+    pub root_dir: PathBuf,
 }
 
 // *************************************************************************** //
 // Functions
 // *************************************************************************** //
 
+/// Answer the questions of all tasks at once, before any of them runs, so that nothing waits
+/// for input in the middle of a run.
+pub fn review_tasks(tasks: &mut TaskList, policy: AnswerPolicy) {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let questions: Vec<(usize, String)> = tasks.iter()
+        .enumerate()
+        .filter_map(|(i, task)| task.review().map(|question| (i, question)))
+        .collect();
+    if questions.is_empty() {
+        return;
+    }
+    let interactive = policy == AnswerPolicy::Ask && std::io::stdin().is_terminal();
+    if !interactive {
+        let answer = match policy {
+            AnswerPolicy::Yes => Some(true),
+            AnswerPolicy::No => Some(false),
+            AnswerPolicy::Ask => {
+                log_warn!("No terminal to ask on, using `on_unsave_unattended`.");
+                None
+            },
+        };
+        for (i, _) in &questions {
+            tasks[*i].resolve(answer);
+        }
+        return;
+    }
+
+    eprintln!("Unsaved work in {} directories:", questions.len());
+    for (n, (i, question)) in questions.iter().enumerate() {
+        eprintln!("  [{}] {} {} ({})", n + 1, tasks[*i].kind(), tasks[*i].source().display(), question);
+    }
+    let selected = loop {
+        eprint!("Save all? [Y/n, or the numbers to save, e.g. `1 3`] ");
+        let _ = std::io::stderr().flush();
+        let mut input = String::new();
+        if std::io::stdin().lock().read_line(&mut input).unwrap_or(0) == 0 {
+            // closed stdin, nobody is there after all
+            break None;
+        }
+        match parse_selection(&input, questions.len()) {
+            Some(selected) => break Some(selected),
+            None => eprintln!("Please answer y, n or numbers between 1 and {}.", questions.len()),
+        }
+    };
+    for (n, (i, _)) in questions.iter().enumerate() {
+        tasks[*i].resolve(selected.as_ref().map(|selected| selected.contains(&(n + 1))));
+    }
+}
+
+/// The 1-based entries selected by an answer to the review, out of `count`.
+fn parse_selection(input: &str, count: usize) -> Option<Vec<usize>> {
+    match input.trim().to_lowercase().as_str() {
+        "" | "y" | "yes" => Some((1..=count).collect()),
+        "n" | "no" => Some(Vec::new()),
+        numbers => numbers
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse().ok().filter(|n| (1..=count).contains(n)))
+            .collect(),
+    }
+}
+
 fn inherit_config(
     this_config: &DionysiusConfig,
     super_config: Option<&DionysiusConfig>
@@ -71,8 +378,41 @@ fn inherit_config(
                         |_| Borg(merged.clone())
                     );
                 },
+                Rsync(this_config) => {
+                    let super_push_config_inner = super_config
+                        .rsync
+                        .as_ref()
+                        .map(|c| c.get_rsync().unwrap());
+                    let merged = this_config.inherit_from(super_push_config_inner);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Rsync(merged.clone())
+                    );
+                },
+                Tar(this_config) => {
+                    let super_push_config_inner = super_config
+                        .tar
+                        .as_ref()
+                        .map(|c| c.get_tar().unwrap());
+                    let merged = this_config.inherit_from(super_push_config_inner);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Tar(merged.clone())
+                    );
+                },
+                Restic(this_config) => {
+                    let super_push_config_inner = super_config
+                        .restic
+                        .as_ref()
+                        .map(|c| c.get_restic().unwrap());
+                    let merged = this_config.inherit_from(super_push_config_inner);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Restic(merged.clone())
+                    );
+                },
                 _ => {
-                    log(LogLevel::Error, format!("Unimplemented inheritance for {:?}", push_config).as_str());
+                    log_error!("Unimplemented inheritance for {:?}", push_config);
                     unreachable!()
                 }
             }
@@ -96,6 +436,27 @@ fn inherit_config(
                         |_| Borg(merged.clone())
                     );
                 },
+                Rsync(this_config) => {
+                    let merged = this_config.inherit_from(None);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Rsync(merged.clone())
+                    );
+                },
+                Tar(this_config) => {
+                    let merged = this_config.inherit_from(None);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Tar(merged.clone())
+                    );
+                },
+                Restic(this_config) => {
+                    let merged = this_config.inherit_from(None);
+                    config_clone.map_at_push_task_configs_mut(
+                        |field_name_opt| field_name_opt == Some(field_name),
+                        |_| Restic(merged.clone())
+                    );
+                },
                 _ => {}
             }
         }
@@ -104,6 +465,29 @@ fn inherit_config(
     config_clone
 }
 
+/// Collect the tasks under `cli_config.root_dir`. Directories that could not be visited are
+/// left to `traversal.errors()`; once `traversal` is cancelled, nothing collected so far is
+/// returned.
+pub async fn collect_all_tasks(
+    task_type_id: &'static str,
+    cli_config: CliTaskConfig,
+    traversal: &Traversal,
+) -> Result<TaskList, Cancelled> {
+    let root_dir = cli_config.root_dir.clone();
+    let task_list = Arc::new(Mutex::new(Vec::new()));
+    let walk = collect_tasks(task_type_id, root_dir.clone(), task_list.clone(), None, None, cli_config, traversal.clone());
+    tokio::select! {
+        biased;
+        // the walk is dropped with everything it collected
+        _ = traversal.cancelled() => return Err(Cancelled),
+        result = walk => if let Err(err) = result {
+            traversal.record(&root_dir, err);
+        },
+    }
+    traversal.finish();
+    Ok(std::mem::take(&mut *task_list.lock().unwrap()))
+}
+
 #[async_recursion]
 pub async fn collect_tasks(
     task_type_id: &'static str,
@@ -112,6 +496,7 @@ pub async fn collect_tasks(
     super_config: Option<DionysiusConfig>,
     super_exclude_list: Option<Arc<Mutex<Vec<PathBuf>>>>,
     cli_config: CliTaskConfig,
+    traversal: Traversal,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 	// println!("current_dir: {:?}", current_dir);
 
@@ -136,56 +521,29 @@ pub async fn collect_tasks(
         }
     }
 
-    // Get configuration
-    let config_path = current_dir.join("dionysius.toml");
-    let mut config;
-    let config_ref;
-    let is_git_repo = current_dir.join(".git").is_dir();
-    let config_exist = config_path.exists();
+    // Look into the directory on the blocking pool
+    let probe_dir = current_dir.clone();
+    let (is_git_repo, config) = traversal.blocking(move || probe_directory(&probe_dir)).await?;
 
     // Early return for non-repo directories 
-    if !config_exist && !is_git_repo {
-        let mut subfolder_futures = Vec::new();
-        
-        for entry in WalkDir::new(&current_dir)
-            .min_depth(1)
-            .max_depth(1)
-            .follow_links(false)
-            .into_iter()
-            .filter_entry(|e| e.file_type().is_dir()) {
-                
-            if let Ok(entry) = entry {
-                let path = entry.path().to_path_buf();
-                let future = tokio::spawn(collect_tasks(
-                    task_type_id,
-                    path,
-                    task_list.clone(),
-                    super_config.clone(),
-                    super_exclude_list.clone(),
-                    cli_config.clone(),
-                ));
-                subfolder_futures.push(future);
-            }
-        }
-
-        let results = join_all(subfolder_futures).await;
-        for res in results {
-            res??;
-        }
+    if config.is_none() && !is_git_repo {
+        process_subdirs(
+            task_type_id,
+            &current_dir,
+            task_list,
+            super_config,
+            super_exclude_list,
+            cli_config,
+            traversal,
+        ).await;
         return Ok(());
     }
 
-	// println!("Repo found: {:?}", current_dir);
-
     // Get config
-    if config_exist {
-		// println!("Repo found: {:?}", current_dir);
-        config = load_config(&config_path).unwrap();
-		// should validate and set that bool correspondingly.
-        config_ref = &config;
-    } else {
-        config_ref = DionysiusConfig::git_default_config();
-    }
+    let config_ref = match config.as_ref() {
+        Some(config) => config,
+        None => DionysiusConfig::git_default_config(),
+    };
 
     // Inherit config
     let config_clone = inherit_config(config_ref, super_config.as_ref());
@@ -195,6 +553,15 @@ pub async fn collect_tasks(
 
     // Create exclude list for current directory
     let current_exclude_list_ref = Arc::new(Mutex::new(Vec::new()));
+    // files the git guard keeps out of borg archives of this and the directories above
+    if is_git_repo {
+        let repo_dir = current_dir.clone();
+        let guarded = traversal.blocking(move || recorded_borg_excludes(&repo_dir)).await;
+        current_exclude_list_ref.lock().unwrap().extend(guarded.iter().cloned());
+        if let Some(super_exclude_list) = super_exclude_list.as_ref() {
+            super_exclude_list.lock().unwrap().extend(guarded);
+        }
+    }
 
     // Process tasks based on config
     // let mut config_clone = config_ref.clone();
@@ -235,7 +602,7 @@ pub async fn collect_tasks(
         //             config_clone.git = Some(Borg(merged));
         //         },
         //         _ => {
-        //             log(LogLevel::Error, format!("Unimplemented inheritance for {:?}; {:?}", push_config, super_config).as_str());
+        //             log_error!("Unimplemented inheritance for {:?}; {:?}", push_config, super_config);
         //             unreachable!()
         //         }
         //     };
@@ -252,13 +619,22 @@ pub async fn collect_tasks(
             Borg(this_config) => {
                 this_config.assets.as_ref().unwrap().on_recursion.clone().unwrap()
             },
+            Rsync(this_config) => {
+                this_config.assets.as_ref().unwrap().on_recursion.clone().unwrap()
+            },
+            Tar(this_config) => {
+                this_config.assets.as_ref().unwrap().on_recursion.clone().unwrap()
+            },
+            Restic(this_config) => {
+                this_config.assets.as_ref().unwrap().on_recursion.clone().unwrap()
+            },
             _ => {
-                log(LogLevel::Error, format!("Unimplemented inheritance for {:?}; {:?}", push_config, super_config).as_str());
+                log_error!("Unimplemented inheritance for {:?}; {:?}", push_config, super_config);
                 unreachable!()
             }
         };
-        // log(LogLevel::Info, format!("{:?}", current_dir).as_str());
-        // log(LogLevel::Info, format!("{:?}", super_config).as_str());
+        // log_debug!("{:?}", current_dir);
+        // log_debug!("{:?}", super_config);
         match push_config {
             Git(this_config) => {
                 // currently, current_exclude_list may be updated by multiple triggers.
@@ -274,24 +650,49 @@ pub async fn collect_tasks(
                         &current_dir,
                         task_list.clone(),
                         Some(config_ref.clone()),
-                        current_exclude_list_ref.clone(),
+                        Some(current_exclude_list_ref.clone()),
                         cli_config.clone(),
-                    ).await?;
+                        traversal.clone(),
+                    ).await;
                     // reap the exclude_list
                     let exclude_list = current_exclude_list_ref.lock().unwrap().clone();
                     let extra_exclude_patterns: Vec<GitIgnorePattern> = cli_exclude_patterns.iter().filter_map(|str| {
                         GitIgnorePattern::try_from(str.clone()).inspect_err(|e| {
-                            log(LogLevel::Warn, e);
+                            log_warn!("{}", e);
                         }).ok()
                     }).collect();
                     // create and append the task
-                    let task = GitSaveTask {
-                        repo_path: current_dir.clone(),
-                        exclude_list,
-                        unsaved_behavior: this_config.assets.as_ref().unwrap().on_unsave.as_ref().unwrap().clone(),
-                        extra_exclude_patterns: extra_exclude_patterns,
-                    };
-                    task_list.lock().unwrap().push(Box::new(task));
+                    // a plain directory configuring its sub-repositories has nothing to push itself
+                    if is_git_repo {
+                        let assets = this_config.assets.as_ref().unwrap();
+                        let task = GitSaveTask {
+                            repo_path: current_dir.clone(),
+                            exclude_list,
+                            unsaved_behavior: assets.on_unsave.as_ref().unwrap().clone(),
+                            unattended_behavior: assets.on_unsave_unattended.clone().unwrap_or(OnUnsave::Ignore),
+                            extra_exclude_patterns: extra_exclude_patterns,
+                            target: GitPushTarget::from_config(this_config.target.as_ref(), &current_dir, &cli_config.root_dir)?,
+                            on_detached: assets.on_detached.clone().unwrap_or_default(),
+                            on_no_upstream: assets.on_no_upstream.clone().unwrap_or_default(),
+                            upstream_remote: assets.upstream_remote.clone().unwrap_or_else(|| "origin".to_string()),
+                            on_behind: assets.on_behind.clone().unwrap_or_default(),
+                            on_diverged: assets.on_diverged.clone().unwrap_or_default(),
+                            recurse_submodules: assets.recurse_submodules.unwrap_or(false),
+                            transport: assets.transport.unwrap_or_default(),
+                            commit_options: {
+                                let defaults = GitCommitOptions::for_source(&current_dir, &cli_config.root_dir);
+                                GitCommitOptions {
+                                    message: assets.autosave_message.clone().unwrap_or(defaults.message),
+                                    fallback_name: assets.fallback_name.clone().unwrap_or(defaults.fallback_name),
+                                    fallback_email: assets.fallback_email.clone().unwrap_or(defaults.fallback_email),
+                                    sign: assets.sign_commits.unwrap_or(false),
+                                    context: defaults.context,
+                                }
+                            },
+                            guard: GitGuard::from_config(assets.guard.as_ref())?,
+                        };
+                        task_list.lock().unwrap().push(Box::new(task));
+                    }
                 } else {
                     return Ok(())
                 }
@@ -310,53 +711,143 @@ pub async fn collect_tasks(
                         &current_dir,
                         task_list.clone(),
                         Some(config_ref.clone()),
-                        current_exclude_list_ref.clone(),
+                        Some(current_exclude_list_ref.clone()),
                         cli_config.clone(),
-                    ).await?;
+                        traversal.clone(),
+                    ).await;
                     // reap the exclude_list
                     let exclude_list = current_exclude_list_ref.lock().unwrap().clone();
-                    let mut extra_exclude_patterns: Vec<BorgPattern> = cli_exclude_patterns.iter().filter_map(|str| {
-                        BorgPattern::try_from(str.clone()).inspect_err(|e| {
-                            log(LogLevel::Warn, e);
-                        }).ok()
-                    }).collect();
-                    if let Some(config_exclude_patterns) = this_config.assets.as_ref().unwrap().exclude_list.clone() {
-                        extra_exclude_patterns.extend(
-                            config_exclude_patterns.iter().filter_map(|str| {
-                                BorgPattern::try_from(str.clone()).inspect_err(|e| {
-                                    log(LogLevel::Warn, e);
-                                }).ok()
-                            })
-                        );
-                    }
-                    if let Some(extra_exclude_modes) = &this_config.assets.as_ref().unwrap().extra_exclude_mode {
-                        let gitignore_path = &current_dir.join(".gitignore");
-                        if extra_exclude_modes.contains(&"git".to_string()) && gitignore_path.is_file() {
-                            let patterns = crate::handlers::exclude::read_gitignore(gitignore_path);
-                            patterns.into_iter().filter_map(|p| {
-                                BorgPattern::try_from(p).inspect_err(|e| {
-                                    log(LogLevel::Warn, e);
-                                }).ok()
-                            }).for_each(|pattern| {
-                                extra_exclude_patterns.push(pattern);
-                            });
-                        }
-                    }
+                    let assets = this_config.assets.as_ref().unwrap();
+                    let extra_exclude_patterns = collect_borg_patterns(
+                        &current_dir,
+                        cli_exclude_patterns,
+                        assets.exclude_list.as_ref(),
+                        assets.extra_exclude_mode.as_ref(),
+                    );
                     // create and append the task
-                    let task = BorgCreateTask {
-                        source: current_dir.clone(),
-                        target: {
-                            match &config_ref.borg {
-                                Some(PushTaskConfig::Borg(borg_conf)) => {
-                                    borg_conf.target.as_ref().unwrap().target.clone().unwrap()
-                                },
-                                _ => panic!(),
-                            }
-                        },
+                    let task = BorgCreateTask::new(
+                        current_dir.clone(),
+                        &cli_config.root_dir,
+                        this_config,
                         exclude_list,
                         extra_exclude_patterns,
-                        options: BorgCreateOptions::default()
-                    };
+                    )?;
+                    task_list.lock().unwrap().push(Box::new(task));
+                } else {
+                    return Ok(())
+                }
+            },
+            Rsync(this_config) => {
+                let should_create_task = apply_recursion_strategy(
+                    &current_dir,
+                    &on_recursion,
+                    super_exclude_list.clone()
+                )?;
+                if should_create_task {
+                    // process subdirectories: collect in subdirectories; update this exclude_list
+                    process_subdirs(
+                        task_type_id,
+                        &current_dir,
+                        task_list.clone(),
+                        Some(config_ref.clone()),
+                        Some(current_exclude_list_ref.clone()),
+                        cli_config.clone(),
+                        traversal.clone(),
+                    ).await;
+                    // reap the exclude_list
+                    let exclude_list = current_exclude_list_ref.lock().unwrap().clone();
+                    let assets = this_config.assets.as_ref().unwrap();
+                    let extra_exclude_patterns = collect_borg_patterns(
+                        &current_dir,
+                        cli_exclude_patterns,
+                        assets.exclude_list.as_ref(),
+                        assets.extra_exclude_mode.as_ref(),
+                    );
+                    // create and append the task
+                    let task = RsyncTask::new(
+                        current_dir.clone(),
+                        this_config.target.as_ref().unwrap(),
+                        exclude_list,
+                        extra_exclude_patterns,
+                    )?;
+                    task_list.lock().unwrap().push(Box::new(task));
+                } else {
+                    return Ok(())
+                }
+            },
+            Tar(this_config) => {
+                let should_create_task = apply_recursion_strategy(
+                    &current_dir,
+                    &on_recursion,
+                    super_exclude_list.clone()
+                )?;
+                if should_create_task {
+                    // process subdirectories: collect in subdirectories; update this exclude_list
+                    process_subdirs(
+                        task_type_id,
+                        &current_dir,
+                        task_list.clone(),
+                        Some(config_ref.clone()),
+                        Some(current_exclude_list_ref.clone()),
+                        cli_config.clone(),
+                        traversal.clone(),
+                    ).await;
+                    // reap the exclude_list
+                    let exclude_list = current_exclude_list_ref.lock().unwrap().clone();
+                    let assets = this_config.assets.as_ref().unwrap();
+                    let extra_exclude_patterns = collect_borg_patterns(
+                        &current_dir,
+                        cli_exclude_patterns,
+                        assets.exclude_list.as_ref(),
+                        assets.extra_exclude_mode.as_ref(),
+                    );
+                    // create and append the task
+                    let task = TarArchiveTask::new(
+                        current_dir.clone(),
+                        &cli_config.root_dir,
+                        this_config.target.as_ref().unwrap(),
+                        exclude_list,
+                        extra_exclude_patterns,
+                    )?;
+                    task_list.lock().unwrap().push(Box::new(task));
+                } else {
+                    return Ok(())
+                }
+            },
+            Restic(this_config) => {
+                let should_create_task = apply_recursion_strategy(
+                    &current_dir,
+                    &on_recursion,
+                    super_exclude_list.clone()
+                )?;
+                if should_create_task {
+                    // process subdirectories: collect in subdirectories; update this exclude_list
+                    process_subdirs(
+                        task_type_id,
+                        &current_dir,
+                        task_list.clone(),
+                        Some(config_ref.clone()),
+                        Some(current_exclude_list_ref.clone()),
+                        cli_config.clone(),
+                        traversal.clone(),
+                    ).await;
+                    // reap the exclude_list
+                    let exclude_list = current_exclude_list_ref.lock().unwrap().clone();
+                    let assets = this_config.assets.as_ref().unwrap();
+                    let extra_exclude_patterns = collect_borg_patterns(
+                        &current_dir,
+                        cli_exclude_patterns,
+                        assets.exclude_list.as_ref(),
+                        assets.extra_exclude_mode.as_ref(),
+                    );
+                    // create and append the task
+                    let task = ResticBackupTask::new(
+                        current_dir.clone(),
+                        this_config.target.as_ref().unwrap(),
+                        exclude_list,
+                        extra_exclude_patterns,
+                        assets.exclude_ignore_case.unwrap_or(false),
+                    )?;
                     task_list.lock().unwrap().push(Box::new(task));
                 } else {
                     return Ok(())
@@ -378,9 +869,10 @@ pub async fn collect_tasks(
                         &current_dir,
                         task_list.clone(),
                         Some(config_ref.clone()),
-                        super_exclude_list.clone().unwrap(),
+                        super_exclude_list.clone(),
                         cli_config.clone(),
-                    ).await?;
+                        traversal.clone(),
+                    ).await;
                     // create and append the task
                     let task = TriggerTask {current_dir: current_dir.clone()};
                     task_list.lock().unwrap().push(Box::new(task));
@@ -395,45 +887,86 @@ pub async fn collect_tasks(
     Ok(())
 }
 
-// TODO: can use this for the trivial subdir case, too
+/// Gather the extra exclude patterns of a borg-like task: those from the command line,
+/// from `exclude_list` in the config and, if `extra_exclude_mode` contains `git`, from `.gitignore`.
+fn collect_borg_patterns(
+    current_dir: &Path,
+    cli_exclude_patterns: &[String],
+    config_exclude_patterns: Option<&Vec<String>>,
+    extra_exclude_modes: Option<&Vec<String>>,
+) -> Vec<BorgPattern> {
+    let mut extra_exclude_patterns: Vec<BorgPattern> = cli_exclude_patterns.iter().filter_map(|str| {
+        BorgPattern::try_from(str.clone()).inspect_err(|e| {
+            log_warn!("{}", e);
+        }).ok()
+    }).collect();
+    if let Some(config_exclude_patterns) = config_exclude_patterns {
+        extra_exclude_patterns.extend(
+            config_exclude_patterns.iter().filter_map(|str| {
+                BorgPattern::try_from(str.clone()).inspect_err(|e| {
+                    log_warn!("{}", e);
+                }).ok()
+            })
+        );
+    }
+    if let Some(extra_exclude_modes) = extra_exclude_modes {
+        let gitignore_path = &current_dir.join(".gitignore");
+        if extra_exclude_modes.contains(&"git".to_string()) && gitignore_path.is_file() {
+            let patterns = crate::handlers::exclude::read_gitignore(gitignore_path);
+            patterns.into_iter().filter_map(|p| {
+                BorgPattern::try_from(p).inspect_err(|e| {
+                    log_warn!("{}", e);
+                }).ok()
+            }).for_each(|pattern| {
+                extra_exclude_patterns.push(pattern);
+            });
+        }
+    }
+    extra_exclude_patterns
+}
+
+/// Collect the tasks of the directories right below `current_dir`, all at once. Directories
+/// that fail are recorded in `traversal` and the others go on.
 async fn process_subdirs(
     task_type_id: &'static str,
-    current_dir: &PathBuf,
+    current_dir: &Path,
     task_list: Arc<Mutex<TaskList>>,
     super_config: Option<DionysiusConfig>,
-    current_exclude_list_ref: Arc<Mutex<Vec<PathBuf>>>,
-    cli_config: CliTaskConfig, // 新增参数
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut subfolder_futures = Vec::new();
-    
-    // Use synchronous walkdir to avoid tokio::fs::read_dir() open too many files
-    for entry in WalkDir::new(current_dir)
-        .min_depth(1)
-        .max_depth(1)
-        .follow_links(false)
-        .into_iter()
-        .filter_entry(|e| e.file_type().is_dir()) {
-            
-        if let Ok(entry) = entry {
-            let path = entry.path().to_path_buf();
-            let future = tokio::spawn(collect_tasks(
-                task_type_id,
-                path,
-                task_list.clone(),
-                super_config.clone(), 
-                Some(current_exclude_list_ref.clone()),
-                cli_config.clone(),
-            ));
-            subfolder_futures.push(future);
+    exclude_list: Option<Arc<Mutex<Vec<PathBuf>>>>,
+    cli_config: CliTaskConfig,
+    traversal: Traversal,
+) {
+    let subdirs = traversal.subdirs(current_dir).await;
+    // polled here rather than spawned, the file system work is bounded by `traversal`
+    let results = join_all(subdirs.iter().map(|path| collect_tasks(
+        task_type_id,
+        path.clone(),
+        task_list.clone(),
+        super_config.clone(),
+        exclude_list.clone(),
+        cli_config.clone(),
+        traversal.clone(),
+    ))).await;
+    for (path, result) in subdirs.iter().zip(results) {
+        if let Err(err) = result {
+            traversal.record(path, err);
         }
     }
+}
 
-    let results = join_all(subfolder_futures).await;
-    for res in results {
-        res??;
+/// Whether `dir` is a git repository, and its `dionysius.toml` if it has one.
+fn probe_directory(dir: &Path) -> Result<(bool, Option<DionysiusConfig>), String> {
+    let is_git_repo = dir.join(".git").is_dir();
+    let config_path = dir.join("dionysius.toml");
+    if config_path.is_symlink() && !config_path.exists() {
+        return Err(format!("{} is a broken symbolic link", config_path.display()));
     }
-
-    Ok(())
+    if !config_path.exists() {
+        return Ok((is_git_repo, None));
+    }
+    load_config(&config_path)
+        .map(|config| (is_git_repo, Some(config)))
+        .map_err(|err| format!("Failed to load {}: {}", config_path.display(), err))
 }
 
 fn apply_recursion_strategy(
@@ -464,8 +997,80 @@ fn apply_recursion_strategy(
             Ok(true)
 		},
 		OnRecursion::Inherit => {
-            log(LogLevel::Error, format!("Unexpected `on_recursion=inherit` in {:?}", current_dir).as_str());
+            log_error!("Unexpected `on_recursion=inherit` in {:?}", current_dir);
             unreachable!()
 		},
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("\n", 3), Some(vec![1, 2, 3]));
+        assert_eq!(parse_selection("Y", 3), Some(vec![1, 2, 3]));
+        assert_eq!(parse_selection("n", 3), Some(vec![]));
+        assert_eq!(parse_selection("1 3", 3), Some(vec![1, 3]));
+        assert_eq!(parse_selection("2,3", 3), Some(vec![2, 3]));
+        assert_eq!(parse_selection("4", 3), None);
+        assert_eq!(parse_selection("maybe", 3), None);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::default();
+        assert!(!summary.has_failure());
+        assert_eq!(summary.to_string(), "0 succeeded, 0 skipped, 0 failed.");
+
+        let task = |dir: &str| TriggerTask { current_dir: PathBuf::from(dir) };
+        summary.record(&task("/a"), Ok(TaskOutcome::Succeeded));
+        summary.record(&task("/b"), Ok(TaskOutcome::Skipped("no upstream".to_string())));
+        assert!(!summary.has_failure());
+        summary.record(&task("/c"), Err(RsyncError::Pattern("invalid pattern").into()));
+        summary.record(&task("/d"), Ok(TaskOutcome::Succeeded));
+        assert!(summary.has_failure());
+        assert_eq!(summary.reports[2].kind, "trigger");
+        assert_eq!(summary.reports[2].source, PathBuf::from("/c"));
+        assert_eq!((summary.succeeded_count(), summary.skipped_count(), summary.failed_count()), (2, 1, 1));
+        assert_eq!(summary.counts(), SummaryCounts { succeeded: 2, skipped: 1, failed: 1 });
+        assert_eq!(summary.to_string(), "\
+[ok]      trigger: /a
+[skipped] trigger: /b (no upstream)
+[failed]  trigger: /c (invalid pattern)
+[ok]      trigger: /d
+2 succeeded, 1 skipped, 1 failed.");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_collect_all_tasks_goes_on_past_errors() {
+        let base = std::env::temp_dir().join(format!("dionysius_traversal_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("good/.git")).unwrap();
+        std::fs::create_dir_all(base.join("plain/nested/.git")).unwrap();
+        std::fs::create_dir_all(base.join("bad")).unwrap();
+        std::fs::write(base.join("bad/dionysius.toml"), "[git").unwrap();
+        std::fs::create_dir_all(base.join("broken")).unwrap();
+        std::os::unix::fs::symlink(base.join("missing.toml"), base.join("broken/dionysius.toml")).unwrap();
+        let cli_config = CliTaskConfig {
+            exclude_patterns: Vec::new(),
+            search_hidden: false,
+            root_dir: base.clone(),
+        };
+
+        let traversal = Traversal::new(2);
+        let tasks = collect_all_tasks("git", cli_config.clone(), &traversal).await.unwrap();
+        let mut sources: Vec<&Path> = tasks.iter().map(|task| task.source()).collect();
+        sources.sort();
+        assert_eq!(sources, vec![base.join("good"), base.join("plain/nested")]);
+        let errors: Vec<PathBuf> = traversal.errors().into_iter().map(|err| err.path).collect();
+        assert_eq!(errors, vec![base.join("bad"), base.join("broken")]);
+
+        let traversal = Traversal::new(2);
+        traversal.cancel();
+        assert_eq!(collect_all_tasks("git", cli_config, &traversal).await.err(), Some(Cancelled));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
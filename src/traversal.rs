@@ -0,0 +1,180 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex};
+use serde::Serialize;
+use tokio::sync::{Notify, Semaphore};
+use walkdir::WalkDir;
+
+/// A directory the traversal could not look into, and why. The rest of the walk goes on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraversalError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for TraversalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// The traversal was interrupted with Ctrl-C before it finished.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interrupted while collecting tasks")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Shared by all directories of one walk: at most `workers` of them touch the file system at
+/// a time, on the blocking pool, and what could not be read is noted instead of ending the walk.
+#[derive(Debug, Clone)]
+pub struct Traversal {
+    workers: Arc<Semaphore>,
+    errors: Arc<Mutex<Vec<TraversalError>>>,
+    cancel: Arc<Notify>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Traversal {
+    pub fn new(workers: usize) -> Self {
+        Traversal {
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            errors: Arc::new(Mutex::new(Vec::new())),
+            cancel: Arc::new(Notify::new()),
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Stop the walk, now or as soon as it starts.
+    pub fn cancel(&self) {
+        self.cancel.notify_one();
+    }
+
+    /// Resolves once `cancel` was called.
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await;
+    }
+
+    /// The walk is over, there is nothing left to cancel.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Cancel the walk at Ctrl-C. The handler stays installed for the rest of the process, so
+    /// once the walk is over, Ctrl-C ends it with the usual status, as it would without one.
+    pub fn cancel_on_ctrl_c(&self) {
+        let traversal = self.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if traversal.finished.load(Ordering::SeqCst) {
+                    std::process::exit(130);
+                }
+                traversal.cancel();
+            }
+        });
+    }
+
+    /// Run `f` on the blocking pool once a worker is free.
+    pub async fn blocking<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.workers.acquire().await.expect("semaphore is never closed");
+        match tokio::task::spawn_blocking(f).await {
+            Ok(value) => value,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// The directories right below `dir`, symbolic links not followed. Entries that cannot be
+    /// read are recorded and left out.
+    pub async fn subdirs(&self, dir: &Path) -> Vec<PathBuf> {
+        let dir = dir.to_path_buf();
+        let (subdirs, errors) = self.blocking(move || {
+            let mut subdirs = Vec::new();
+            let mut errors = Vec::new();
+            for entry in WalkDir::new(&dir)
+                .min_depth(1)
+                .max_depth(1)
+                .follow_links(false)
+                .into_iter()
+                .filter_entry(|e| e.file_type().is_dir()) {
+                match entry {
+                    Ok(entry) => subdirs.push(entry.into_path()),
+                    Err(err) => errors.push(TraversalError {
+                        path: err.path().map_or_else(|| dir.clone(), Path::to_path_buf),
+                        message: err.io_error().map_or_else(|| err.to_string(), |io| io.to_string()),
+                    }),
+                }
+            }
+            (subdirs, errors)
+        }).await;
+        self.errors.lock().unwrap().extend(errors);
+        subdirs
+    }
+
+    pub fn record(&self, path: &Path, message: impl fmt::Display) {
+        self.errors.lock().unwrap().push(TraversalError {
+            path: path.to_path_buf(),
+            message: message.to_string(),
+        });
+    }
+
+    /// What was recorded so far, by path.
+    pub fn errors(&self) -> Vec<TraversalError> {
+        let mut errors = self.errors.lock().unwrap().clone();
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_workers_bound_blocking_calls() {
+        let traversal = Traversal::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let calls = (0..6).map(|_| {
+            let (running, max_running) = (running.clone(), max_running.clone());
+            traversal.blocking(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(30));
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        futures::future::join_all(calls).await;
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_subdirs() {
+        let base = std::env::temp_dir().join(format!("dionysius_subdirs_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("a")).unwrap();
+        std::fs::create_dir_all(base.join("b")).unwrap();
+        std::fs::write(base.join("file"), "").unwrap();
+        std::os::unix::fs::symlink(base.join("a"), base.join("link")).unwrap();
+
+        let traversal = Traversal::new(1);
+        let mut subdirs = traversal.subdirs(&base).await;
+        subdirs.sort();
+        assert_eq!(subdirs, vec![base.join("a"), base.join("b")]);
+        assert!(traversal.subdirs(&base.join("missing")).await.is_empty());
+        assert_eq!(traversal.errors().len(), 1);
+        assert_eq!(traversal.errors()[0].path, base.join("missing"));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
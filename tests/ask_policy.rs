@@ -0,0 +1,71 @@
+//! Drives `dionysius push git` dry runs on a repository with `on_unsave = "ask"` and unsaved
+//! work, with stdin not a terminal.
+
+mod common;
+
+use std::fs;
+use std::process::{Command, Output, Stdio};
+use common::{git, TempDir};
+
+struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    fn new(name: &str, assets_extra: &str) -> Self {
+        let dir = TempDir::new("ask", name);
+        let repo = dir.repo("repo");
+        fs::write(repo.join("file.txt"), "unsaved").unwrap();
+        fs::write(
+            repo.join("dionysius.toml"),
+            format!("[git.assets]\non_unsave = \"ask\"\n{}\n", assets_extra),
+        ).unwrap();
+        git(&repo, &["add", "dionysius.toml"]);
+        git(&repo, &["commit", "-q", "-m", "config"]);
+        Fixture { dir }
+    }
+
+    fn push(&self, flags: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .arg("push")
+            .args(flags)
+            .args(["git", "-d"])
+            .arg(self.dir.join("repo"))
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+}
+
+/// Whether the dry run stages the unsaved work.
+fn saves(output: &Output) -> bool {
+    String::from_utf8_lossy(&output.stdout).contains("\"add\" \".\"")
+}
+
+#[test]
+fn test_yes_and_no_answer_without_asking() {
+    let fixture = Fixture::new("flags", "");
+    let output = fixture.push(&["--yes"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(saves(&output));
+
+    let output = fixture.push(&["--no"]);
+    assert!(output.status.success());
+    assert!(!saves(&output));
+
+    assert!(!fixture.push(&["--yes", "--no"]).status.success());
+}
+
+#[test]
+fn test_unattended_default_without_terminal() {
+    let fixture = Fixture::new("unattended", "");
+    let output = fixture.push(&[]);
+    assert!(output.status.success());
+    assert!(!saves(&output));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No terminal"));
+
+    let fixture = Fixture::new("unattended_save", "on_unsave_unattended = \"save\"");
+    let output = fixture.push(&["--ask"]);
+    assert!(output.status.success());
+    assert!(saves(&output));
+}
@@ -0,0 +1,113 @@
+//! Drives `dionysius push -e borg` against a fake `borg` on `PATH`, which logs its
+//! invocations and keeps a "repository" as a plain directory.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+use common::TempDir;
+
+const FAKE_BORG: &str = r#"#!/bin/sh
+echo "$*" >> "$FAKE_BORG_LOG"
+case "$1" in
+    info)
+        if [ -d "$2" ]; then exit 0; fi
+        echo "Repository $2 does not exist." >&2
+        exit 2
+        ;;
+    init) mkdir -p "$4" ;;
+    check) [ -z "$FAKE_BORG_CHECK_FAILS" ] || exit 2 ;;
+esac
+exit 0
+"#;
+
+struct Fixture {
+    root: TempDir,
+}
+
+impl Fixture {
+    fn new(name: &str, target_extra: &str) -> Self {
+        let root = TempDir::new("borg", name);
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("source")).unwrap();
+        fs::write(root.join("bin/borg"), FAKE_BORG).unwrap();
+        fs::set_permissions(root.join("bin/borg"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(
+            root.join("source/dionysius.toml"),
+            format!(
+                "[borg.target]\nmode = \"path\"\ntarget = \"{}\"\n{}\n",
+                root.join("repo").display(),
+                target_extra
+            ),
+        ).unwrap();
+        Fixture { root }
+    }
+
+    fn push(&self, envs: &[(&str, &str)]) -> Output {
+        let path = format!("{}:{}", self.root.join("bin").display(), std::env::var("PATH").unwrap_or_default());
+        Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .args(["push", "-e", "borg", "-d"])
+            .arg(self.root.join("source"))
+            .env("PATH", path)
+            .env("FAKE_BORG_LOG", self.root.join("borg.log"))
+            .env("XDG_STATE_HOME", self.root.join("state"))
+            .envs(envs.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    fn borg_calls(&self) -> Vec<String> {
+        let log = fs::read_to_string(self.root.join("borg.log")).unwrap_or_default();
+        log.lines().map(|line| line.split(' ').next().unwrap().to_string()).collect()
+    }
+}
+
+#[test]
+fn test_missing_repository_fails_without_auto_init() {
+    let fixture = Fixture::new("missing", "");
+    let output = fixture.push(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+    assert_eq!(fixture.borg_calls(), vec!["info"]);
+}
+
+#[test]
+fn test_auto_init_creates_repository() {
+    let fixture = Fixture::new("init", "auto_init = true\nencryption = \"none\"");
+    let output = fixture.push(&[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fixture.borg_calls(), vec!["info", "init", "create"]);
+    assert!(fixture.root.join("repo").is_dir());
+    assert!(fs::read_to_string(fixture.root.join("borg.log")).unwrap().contains("init --encryption none"));
+}
+
+#[test]
+fn test_auto_init_requires_encryption() {
+    let fixture = Fixture::new("noenc", "auto_init = true");
+    let output = fixture.push(&[]);
+    assert!(!output.status.success());
+    assert!(fixture.borg_calls().is_empty());
+}
+
+#[test]
+fn test_check_every_n_runs() {
+    let fixture = Fixture::new("check", "auto_init = true\nencryption = \"none\"\ncheck_every = 2");
+    assert!(fixture.push(&[]).status.success());
+    assert!(fixture.push(&[]).status.success());
+    assert!(fixture.push(&[]).status.success());
+    assert_eq!(fixture.borg_calls(), vec![
+        "info", "init", "create",
+        "info", "create", "check",
+        "info", "create",
+    ]);
+
+    // a failed check fails the run and is recorded
+    let output = fixture.push(&[("FAKE_BORG_CHECK_FAILS", "1")]);
+    assert!(!output.status.success());
+    let state_dir = fixture.root.join("state/dionysius/borg-check");
+    let state = fs::read_dir(&state_dir).unwrap().next().unwrap().unwrap().path();
+    let state = fs::read_to_string(state).unwrap();
+    assert!(state.contains("runs_since_check = 0"), "{}", state);
+    assert!(state.contains("last_check_ok = false"), "{}", state);
+}
@@ -0,0 +1,62 @@
+//! Shared by the integration tests: a scratch directory removed on drop, and `git` run with a
+//! fixed identity.
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `dionysius_<area>_<name>_<pid>` under the temporary directory, emptied on creation and
+/// removed on drop.
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(area: &str, name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dionysius_{}_{}_{}", area, name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+
+    /// A repository at `name` on branch `main`, with `file.txt` committed.
+    pub fn repo(&self, name: &str) -> PathBuf {
+        let repo = self.join(name);
+        init_repo(&repo);
+        repo
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Run git in `dir` and return its trimmed standard output.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Create `dir` as a repository on branch `main`, with `file.txt` committed.
+pub fn init_repo(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "-q", "-b", "main"]);
+    fs::write(dir.join("file.txt"), "content").unwrap();
+    git(dir, &["add", "file.txt"]);
+    git(dir, &["commit", "-q", "-m", "init"]);
+}
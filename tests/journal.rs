@@ -0,0 +1,89 @@
+//! Drives `dionysius push -e git` on a repository tracking a local bare origin, then reads
+//! the recorded run back through `status` and `push --preview`.
+
+mod common;
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use serde_json::Value;
+use common::{git, TempDir};
+
+struct Fixture {
+    base: TempDir,
+    repo: PathBuf,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let base = TempDir::new("journal", name);
+        git(&base.path, &["init", "-q", "--bare", "-b", "main", "origin.git"]);
+        let repo = base.repo("repo");
+        git(&repo, &["remote", "add", "origin", base.join("origin.git").to_str().unwrap()]);
+        git(&repo, &["push", "-q", "-u", "origin", "main"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "ahead"]);
+        Fixture { base, repo }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .args(args)
+            .arg(&self.repo)
+            .env("XDG_STATE_HOME", self.base.join("state"))
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+
+    fn stdout(&self, args: &[&str]) -> String {
+        String::from_utf8(self.run(args).stdout).unwrap()
+    }
+}
+
+#[test]
+fn test_runs_are_recorded() {
+    let fixture = Fixture::new("recorded");
+    let preview = fixture.stdout(&["push", "-p", "git", "-d"]);
+    assert!(preview.contains("never backed up (stale)"), "{}", preview);
+    // dry runs are not recorded
+    fixture.run(&["push", "git", "-d"]);
+    assert!(fixture.stdout(&["status", "-d"]).contains("never (stale)"));
+
+    fixture.run(&["push", "-e", "git", "-d"]);
+    let head = git(&fixture.repo, &["rev-parse", "HEAD"]);
+    let document: Value = serde_json::from_str(&fixture.stdout(&["status", "--format", "json", "-d"])).unwrap();
+    let task = &document["tasks"][0];
+    assert_eq!(task["journal"]["pushed_commit"], head.as_str());
+    assert_eq!(task["journal"]["last_result"], "succeeded");
+    assert_eq!(task["stale"], false);
+
+    let preview = fixture.stdout(&["push", "-p", "git", "-d"]);
+    assert!(preview.contains("last backed up 0s ago") || preview.contains("last backed up 1s ago"), "{}", preview);
+    assert!(!preview.contains("stale"), "{}", preview);
+    assert!(fixture.stdout(&["status", "--stale", "-d"]).lines().nth(1).unwrap().is_empty());
+}
+
+#[test]
+fn test_failures_keep_last_success() {
+    let fixture = Fixture::new("failure");
+    fixture.run(&["push", "-e", "git", "-d"]);
+    // the upstream moved on, so the next push is rejected
+    let other = fixture.base.join("other");
+    git(&fixture.base.path, &["clone", "-q", "origin.git", "other"]);
+    git(&other, &["commit", "-q", "--allow-empty", "-m", "elsewhere"]);
+    git(&other, &["push", "-q"]);
+    git(&fixture.repo, &["commit", "-q", "--allow-empty", "-m", "diverged"]);
+    Command::new(env!("CARGO_BIN_EXE_dionysius"))
+        .args(["push", "-e", "git", "-d"])
+        .arg(&fixture.repo)
+        .env("XDG_STATE_HOME", fixture.base.join("state"))
+        .output()
+        .unwrap();
+
+    let document: Value = serde_json::from_str(&fixture.stdout(&["status", "--format", "json", "-d"])).unwrap();
+    let journal = &document["tasks"][0]["journal"];
+    assert_ne!(journal["last_result"], "succeeded");
+    assert!(journal["last_success"].is_string());
+    assert_ne!(journal["pushed_commit"], git(&fixture.repo, &["rev-parse", "HEAD"]).as_str());
+}
@@ -0,0 +1,104 @@
+//! Drives `dionysius --format json|ndjson` over a repository with unsaved work and checks the
+//! emitted documents.
+
+mod common;
+
+use std::fs;
+use std::process::{Command, Stdio};
+use serde_json::Value;
+use common::{git, TempDir};
+
+struct Fixture {
+    root: TempDir,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let root = TempDir::new("json", name);
+        git(&root.path, &["init", "-q", "-b", "main"]);
+        fs::write(root.join("dionysius.toml"), "[git.assets]\non_unsave = \"save\"\n").unwrap();
+        git(&root.path, &["add", "dionysius.toml"]);
+        git(&root.path, &["commit", "-q", "-m", "init"]);
+        fs::write(root.join("dionysius.toml"), "[git.assets]\non_unsave = \"save\"\n# changed\n").unwrap();
+        Fixture { root }
+    }
+
+    fn run(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .args(args)
+            .arg(&self.root.path)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+#[test]
+fn test_dry_run_as_json_document() {
+    let fixture = Fixture::new("document");
+    let document: Value = serde_json::from_str(&fixture.run(&["--format", "json", "push", "git", "-d"])).unwrap();
+    assert_eq!(document["version"], 1);
+    assert_eq!(document["summary"]["skipped"], 1);
+
+    let task = &document["tasks"][0];
+    assert_eq!(task["version"], 1);
+    assert_eq!(task["kind"], "git");
+    assert_eq!(task["target"], "upstream");
+    assert_eq!(task["status"]["git"]["head"], serde_json::json!({"state": "branch", "ref": "refs/heads/main"}));
+    assert_eq!(task["status"]["git"]["work_status"]["workdir_unsaved"], true);
+    assert!(task["commands"][0].as_str().unwrap().contains("\"add\" \".\""));
+    assert_eq!(task["outcome"], serde_json::json!({"result": "skipped", "reason": "no upstream"}));
+}
+
+#[test]
+fn test_ndjson_lines_and_preview() {
+    let fixture = Fixture::new("lines");
+    let stdout = fixture.run(&["push", "--format", "ndjson", "git", "-d"]);
+    let lines: Vec<Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["kind"], "git");
+    assert_eq!(lines[1], serde_json::json!({"version": 1, "summary": {"succeeded": 0, "skipped": 1, "failed": 0}}));
+
+    // nothing has run in a preview
+    let document: Value = serde_json::from_str(&fixture.run(&["push", "-p", "--format", "json", "git", "-d"])).unwrap();
+    assert!(document.get("summary").is_none());
+    assert_eq!(document["tasks"][0]["outcome"], Value::Null);
+
+    let document: Value = serde_json::from_str(&fixture.run(&["status", "--format", "json", "--dirty", "-d"])).unwrap();
+    assert_eq!(document["tasks"].as_array().unwrap().len(), 1);
+    assert_eq!(document["tasks"][0]["status"]["git"]["upstream_counts"], Value::Null);
+}
+
+#[test]
+fn test_exclude_errors_are_reported() {
+    let root = TempDir::new("json", "trigger");
+    fs::write(root.join("dionysius.toml"), "\
+[trigger.assets]
+on_recursion = \"standalone\"
+
+[rsync.target]
+mode = \"path\"
+target = \"/nonexistent/mirror\"
+
+[rsync.assets]
+trigger_by = [\"trigger\"]
+on_recursion = \"standalone\"
+").unwrap();
+
+    // rsync has no regular expressions
+    let output = Command::new(env!("CARGO_BIN_EXE_dionysius"))
+        .args(["push", "-p", "--format", "json", "-x", "re:\\.o$", "trigger", "-d"])
+        .arg(&root.path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let document: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tasks = document["tasks"].as_array().unwrap();
+    let trigger = tasks.iter().find(|task| task["kind"] == "trigger").unwrap();
+    assert_eq!(trigger["exclude"], serde_json::json!([]));
+    assert_eq!(trigger["exclude_error"], Value::Null);
+    let rsync = tasks.iter().find(|task| task["kind"] == "rsync").unwrap();
+    assert!(rsync["exclude_error"].as_str().unwrap().contains("regex"));
+}
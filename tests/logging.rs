@@ -0,0 +1,73 @@
+//! Drives `dionysius push git` dry runs with the logging flags on a repository without an
+//! upstream.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use serde_json::Value;
+use common::TempDir;
+
+struct Fixture {
+    base: TempDir,
+    repo: PathBuf,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let base = TempDir::new("logging", name);
+        let repo = base.repo("repo");
+        Fixture { base, repo }
+    }
+
+    fn push(&self, flags: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .args(flags)
+            .args(["push", "git", "-d"])
+            .arg(&self.repo)
+            .output()
+            .unwrap()
+    }
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_verbosity_and_task_context() {
+    let fixture = Fixture::new("verbosity");
+    let output = fixture.push(&[]);
+    assert!(output.status.success());
+    let warning = stderr(&output).lines().find(|line| line.contains("has no upstream")).unwrap().to_string();
+    assert!(warning.contains("WARN"), "{}", warning);
+    assert!(warning.contains(&format!("source={}", fixture.repo.display())), "{}", warning);
+    assert!(!stderr(&output).contains("Collected"));
+
+    assert!(stderr(&fixture.push(&["-v"])).contains("Collected 1 tasks"));
+    assert!(stderr(&fixture.push(&["-q"])).contains("has no upstream"));
+    assert!(!stderr(&fixture.push(&["-qq"])).contains("has no upstream"));
+    assert!(!fixture.push(&["-v", "-q"]).status.success());
+}
+
+#[test]
+fn test_json_log_file() {
+    let fixture = Fixture::new("file");
+    let log_file = fixture.base.join("logs/dionysius.log");
+    let output = fixture.push(&[
+        "--log-format", "json", "--log-file", log_file.to_str().unwrap(), "--log-rotation", "never",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let lines: Vec<Value> = fs::read_to_string(&log_file).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let warning = lines.iter().find(|line| line["level"] == "WARN").unwrap();
+    assert_eq!(warning["fields"]["message"], "Branch main has no upstream.");
+    assert_eq!(warning["spans"][0]["kind"], "git");
+    assert_eq!(warning["spans"][0]["source"], fixture.repo.to_str().unwrap());
+    // the same events on stderr
+    assert!(stderr(&output).lines().all(|line| line.starts_with('{') || line.starts_with('[') || line.contains("succeeded")));
+}
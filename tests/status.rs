@@ -0,0 +1,86 @@
+//! Drives `dionysius status` over a directory of repositories in different states.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::{git, init_repo, TempDir};
+
+struct Fixture {
+    base: TempDir,
+}
+
+impl Fixture {
+    /// `root/ahead` is one commit ahead of `origin.git`, `root/clean` is a clone of it,
+    /// `root/dirty` has no upstream and unsaved work, `root/empty` has no commits.
+    fn new(name: &str) -> Self {
+        let base = TempDir::new("status", name);
+        let root = base.join("root");
+        fs::create_dir_all(&root).unwrap();
+        git(&base.path, &["init", "-q", "--bare", "-b", "main", "origin.git"]);
+
+        let ahead = root.join("ahead");
+        init_repo(&ahead);
+        git(&ahead, &["remote", "add", "origin", base.join("origin.git").to_str().unwrap()]);
+        git(&ahead, &["push", "-q", "-u", "origin", "main"]);
+        git(&root, &["clone", "-q", base.join("origin.git").to_str().unwrap(), "clean"]);
+        git(&ahead, &["commit", "-q", "--allow-empty", "-m", "ahead"]);
+
+        let dirty = root.join("dirty");
+        init_repo(&dirty);
+        fs::write(dirty.join("file.txt"), "unsaved").unwrap();
+
+        let empty = root.join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        git(&empty, &["init", "-q", "-b", "main"]);
+
+        Fixture { base }
+    }
+
+    /// The repositories listed by `dionysius status` with `flags`.
+    fn listed(&self, flags: &[&str]) -> Vec<String> {
+        let output = Command::new(env!("CARGO_BIN_EXE_dionysius"))
+            .args(["status", "-d"])
+            .arg(self.base.join("root"))
+            .args(flags)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect()
+    }
+}
+
+#[test]
+fn test_status_filters() {
+    let fixture = Fixture::new("filters");
+    assert_eq!(fixture.listed(&[]), vec!["ahead", "clean", "dirty", "empty"]);
+    assert_eq!(fixture.listed(&["--dirty"]), vec!["dirty"]);
+    assert_eq!(fixture.listed(&["--unpushed"]), vec!["ahead"]);
+    assert_eq!(fixture.listed(&["--no-upstream"]), vec!["dirty", "empty"]);
+    assert_eq!(fixture.listed(&["--dirty", "--unpushed"]), vec!["ahead", "dirty"]);
+}
+
+#[test]
+fn test_status_columns_and_legend() {
+    let fixture = Fixture::new("columns");
+    let output = Command::new(env!("CARGO_BIN_EXE_dionysius"))
+        .args(["status", "-d"])
+        .arg(fixture.base.join("root"))
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = |name: &str| stdout.lines().find(|line| line.starts_with(name)).unwrap().to_string();
+    assert!(line("ahead").contains("+1 -0"), "{}", stdout);
+    // the columns after the flags line up with their headers
+    let header = stdout.lines().next().unwrap();
+    assert_eq!(line("ahead").find("+1 -0"), header.find("UPSTREAM"), "{}", stdout);
+    assert!(line("empty").contains("main (unborn)"), "{}", stdout);
+    assert!(line("dirty").contains("W"), "{}", stdout);
+    assert!(stdout.contains("Flags: [repository] [work]"), "{}", stdout);
+}